@@ -0,0 +1,150 @@
+use canonical_json::ser::to_string_into;
+use canonical_json::{canonicalize_slice, to_string, CanonicalFormatter};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::{json, Value};
+
+/// Object members serialized in the given order.
+struct Members(Vec<(String, Value)>);
+
+impl Serialize for Members {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in &self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+fn serialize<T: Serialize>(input: &T) -> Vec<u8> {
+    let mut serializer = serde_json::Serializer::with_formatter(vec![], CanonicalFormatter::new());
+    input.serialize(&mut serializer).unwrap();
+    serializer.into_inner()
+}
+
+fn members(count: usize) -> Vec<(String, Value)> {
+    (0..count)
+        .map(|i| {
+            (
+                format!("key-{:06}", i),
+                json!({ "id": i, "title": "record" }),
+            )
+        })
+        .collect()
+}
+
+fn bench_object_order(c: &mut Criterion) {
+    let mut group = c.benchmark_group("object_order");
+    for count in [16, 1024] {
+        let sorted = Members(members(count));
+        // deterministic shuffle: interleave both halves, reversed
+        let mut shuffled = members(count);
+        shuffled.reverse();
+        let (left, right) = shuffled.split_at(count / 2);
+        let shuffled = Members(
+            left.iter()
+                .zip(right)
+                .flat_map(|(a, b)| vec![b.clone(), a.clone()])
+                .collect(),
+        );
+        assert_eq!(serialize(&sorted), serialize(&shuffled));
+
+        group.throughput(Throughput::Bytes(serialize(&sorted).len() as u64));
+        group.bench_with_input(BenchmarkId::new("sorted", count), &sorted, |b, input| {
+            b.iter(|| serialize(input))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("shuffled", count),
+            &shuffled,
+            |b, input| b.iter(|| serialize(input)),
+        );
+    }
+    group.finish();
+}
+
+fn bench_wide_object(c: &mut Criterion) {
+    let mut group = c.benchmark_group("wide_object");
+    let count = 20_000;
+    // members in reverse order, so that every one of them has to move
+    let mut reversed: Vec<(String, Value)> = (0..count)
+        .map(|i| (format!("{:x}", i * 7919), json!(i)))
+        .collect();
+    reversed.sort_by(|a, b| b.0.cmp(&a.0));
+    let reversed = Members(reversed);
+
+    group.throughput(Throughput::Elements(count));
+    group.bench_function("reversed", |b| b.iter(|| serialize(&reversed)));
+    group.finish();
+}
+
+fn bench_string_heavy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("string_heavy");
+    let records: Vec<Value> = (0..1000)
+        .map(|i| {
+            json!({
+                "id": format!("record-{:06}", i),
+                "title": "The quick brown fox jumps over the lazy dog",
+                "description": "Lorem ipsum dolor sit amet, consectetur adipiscing elit",
+                "tags": ["alpha", "beta", "gamma", "delta"],
+            })
+        })
+        .collect();
+    let input = serde_json::to_vec(&records).unwrap();
+    let value: Value = serde_json::from_slice(&input).unwrap();
+    assert_eq!(
+        canonicalize_slice(&input).unwrap(),
+        to_string(&value).unwrap()
+    );
+
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("value", |b| {
+        b.iter(|| {
+            let value: Value = serde_json::from_slice(&input).unwrap();
+            to_string(&value).unwrap()
+        })
+    });
+    group.bench_function("borrowed", |b| {
+        b.iter(|| canonicalize_slice(&input).unwrap())
+    });
+    // to_string_into checks every chunk written as UTF-8 on its way into
+    // the String, to_string the whole output once: both stayed within the
+    // noise of writing the same output to a Vec<u8> (about 1.4ms each)
+    group.bench_function("into_string", |b| {
+        let mut out = String::new();
+        b.iter(|| {
+            out.clear();
+            to_string_into(&mut out, &value).unwrap()
+        })
+    });
+    group.bench_function("to_string", |b| b.iter(|| to_string(&value).unwrap()));
+
+    // a single large string needing no escapes: skipping the final UTF-8
+    // check of the output made no difference beyond noise here, as scanning
+    // the string for escapes and copying it dominate
+    let large = json!("plain ASCII text, no escapes needed. ".repeat(50_000));
+    group.bench_function("large_string", |b| b.iter(|| to_string(&large).unwrap()));
+
+    // about 10MB of text needing escapes in every line
+    let escaped = json!("line with \"quotes\", a tab\t, é and \u{1f600}\n".repeat(250_000));
+    group.bench_function("escape_heavy", |b| b.iter(|| to_string(&escaped).unwrap()));
+    group.finish();
+}
+
+fn bench_number_heavy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("number_heavy");
+    let floats: Vec<f64> = (0..100_000).map(|i| i as f64 * 1.5 + 0.25).collect();
+
+    group.throughput(Throughput::Elements(floats.len() as u64));
+    group.bench_function("floats", |b| b.iter(|| to_string(&floats).unwrap()));
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_object_order,
+    bench_wide_object,
+    bench_string_heavy,
+    bench_number_heavy
+);
+criterion_main!(benches);
@@ -1,20 +1,31 @@
-use canonical_json::ser::to_string;
-use serde_json;
-use serde_json::Value;
+use canonical_json::stream::canonicalize_from_reader;
 
 use std::env;
 use std::fs::File;
 use std::io::BufReader;
+use std::process;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: demo <file.json>");
+            process::exit(2);
+        }
+    };
 
     // Open the file in read-only mode with buffer.
-    let file = File::open(&args[1]).unwrap();
+    let file = File::open(&path).unwrap_or_else(|error| {
+        eprintln!("cannot open {}: {}", path, error);
+        process::exit(1);
+    });
     let reader = BufReader::new(file);
 
-    // Read the JSON contents of the file as an instance of `User`.
-    let v: Value = serde_json::from_reader(reader).unwrap();
-
-    print!("{}", to_string(&v).unwrap());
+    match canonicalize_from_reader(reader) {
+        Ok(canonical) => print!("{}", canonical),
+        Err(error) => {
+            eprintln!("cannot canonicalize {}: {}", path, error);
+            process::exit(1);
+        }
+    }
 }
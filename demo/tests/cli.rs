@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Runs the demo on a file holding `contents`.
+fn run(name: &str, contents: &str) -> Output {
+    let path: PathBuf = tmp_dir().join(name);
+    fs::write(&path, contents).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_demo"))
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+fn tmp_dir() -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_valid_file() {
+    let output = run("valid.json", "{\"b\": [1.50, \"\\u00e9\"], \"a\": null}\n");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{\"a\":null,\"b\":[1.5E0,\"é\"]}"
+    );
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_malformed_file() {
+    let output = run("malformed.json", "{\"a\": [1, 2}");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("cannot canonicalize "), "{}", stderr);
+    assert!(stderr.contains("malformed.json"), "{}", stderr);
+    assert!(stderr.contains("line 1 column 12"), "{}", stderr);
+}
+
+#[test]
+fn test_missing_file() {
+    let output = Command::new(env!("CARGO_BIN_EXE_demo"))
+        .arg(tmp_dir().join("missing.json"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("cannot open "), "{}", stderr);
+}
@@ -0,0 +1,27 @@
+//! Canonical output must never contain a raw byte below 0x20: control
+//! characters are only allowed in their escaped form.
+#![no_main]
+use canonical_json::to_string;
+use libfuzzer_sys::fuzz_target;
+use serde_json::Value;
+
+fn check(value: &Value) {
+    let serialized = to_string(value).unwrap();
+    assert!(
+        serialized.bytes().all(|byte| byte >= 0x20),
+        "unescaped control byte in {:?}",
+        serialized
+    );
+}
+
+fuzz_target!(|data: &[u8]| {
+    // the raw input as a string value, and as a key
+    let text = String::from_utf8_lossy(data).into_owned();
+    check(&Value::String(text.clone()));
+    check(&Value::Object(std::iter::once((text, Value::Null)).collect()));
+
+    // the input as a JSON document, when it parses
+    if let Ok(value) = serde_json::from_slice::<Value>(data) {
+        check(&value);
+    }
+});
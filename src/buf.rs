@@ -0,0 +1,139 @@
+//! Reusable buffer for serializing many values.
+use crate::ser::{CanonicalJSONError, Canonicalizer};
+use serde::ser::Serialize;
+use std::io::{self, Write};
+use std::ops::Deref;
+
+/// Growable byte buffer receiving canonical JSON.
+///
+/// Its allocation is kept across [CanonicalBuf::clear], so that a single buffer
+/// can serve a loop over many values.
+///
+/// # Example
+///
+/// ```
+/// use canonical_json::CanonicalBuf;
+/// use serde_json::json;
+///
+/// let mut buf = CanonicalBuf::new();
+/// for (value, expected) in [(json!({"b": 1, "a": 2}), r#"{"a":2,"b":1}"#), (json!([]), "[]")] {
+///     buf.clear();
+///     buf.write_value(&value).unwrap();
+///     assert_eq!(buf.as_bytes(), expected.as_bytes());
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CanonicalBuf {
+    canonicalizer: Canonicalizer,
+    bytes: Vec<u8>,
+}
+
+impl CanonicalBuf {
+    /// Empty buffer serializing with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Empty buffer serializing with the options of `canonicalizer`.
+    pub fn with_canonicalizer(canonicalizer: Canonicalizer) -> Self {
+        CanonicalBuf {
+            canonicalizer,
+            bytes: vec![],
+        }
+    }
+
+    /// Appends the canonical form of `value`.
+    ///
+    /// On error, the buffer may hold part of the output.
+    pub fn write_value<V>(&mut self, value: &V) -> Result<(), CanonicalJSONError>
+    where
+        V: ?Sized + Serialize,
+    {
+        self.canonicalizer.write_canonical(&mut self.bytes, value)
+    }
+
+    /// Empties the buffer, keeping its allocation.
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+    }
+
+    /// The bytes written so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Copy of the bytes written so far, as a String.
+    ///
+    /// Fails with [CanonicalJSONError::Utf8Error] only if invalid UTF-8 was
+    /// written through [io::Write].
+    pub fn to_string(&self) -> Result<String, CanonicalJSONError> {
+        Ok(String::from_utf8(self.bytes.clone())?)
+    }
+
+    /// The underlying bytes.
+    pub fn into_inner(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl Deref for CanonicalBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Write for CanonicalBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CanonicalBuf;
+    use crate::ser::{to_string, Canonicalizer};
+    use serde_json::json;
+    use std::io::Write;
+
+    #[test]
+    fn test_canonical_buf_reuse() {
+        let values = vec![
+            json!({ "id": "1", "a": [1.5, null] }),
+            json!("I ❤ testing"),
+            json!({}),
+            json!({ "z": { "y": true, "x": false } }),
+        ];
+        let mut buf = CanonicalBuf::new();
+        for value in &values {
+            buf.clear();
+            buf.write_value(value).unwrap();
+            assert_eq!(buf.to_string().unwrap(), to_string(value).unwrap());
+            assert_eq!(&buf[..], buf.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_canonical_buf_appends() {
+        let mut buf = CanonicalBuf::with_canonicalizer(Canonicalizer::new().ascii_only(true));
+        buf.write_value(&json!({ "b": "é", "a": 1 })).unwrap();
+        buf.write_all(b"\n").unwrap();
+        buf.write_value(&json!([2])).unwrap();
+        assert_eq!(buf.into_inner(), b"{\"a\":1,\"b\":\"\\u00e9\"}\n[2]");
+    }
+
+    #[test]
+    fn test_canonical_buf_invalid_utf8() {
+        let mut buf = CanonicalBuf::new();
+        buf.write_all(&[b'"', 0xff, b'"']).unwrap();
+        assert!(matches!(
+            buf.to_string(),
+            Err(crate::ser::CanonicalJSONError::Utf8Error(_))
+        ));
+    }
+}
@@ -0,0 +1,145 @@
+//! Memoized canonicalization of shared values.
+use crate::ser::{CanonicalJSONError, Canonicalizer};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+/// Canonicalizer remembering the output for the values it has seen, by
+/// identity of their `Arc`.
+///
+/// Values are looked up by pointer, not by content: two equal values in
+/// different `Arc`s are canonicalized separately. The cache keeps a clone of
+/// every `Arc` it holds an output for, so that the pointer cannot be reused for
+/// another value while the entry lives. Once `capacity` entries are cached, the
+/// oldest one is evicted.
+///
+/// # Example
+///
+/// ```
+/// use canonical_json::cache::CachedCanonicalizer;
+/// use serde_json::json;
+/// use std::sync::Arc;
+///
+/// let mut cache = CachedCanonicalizer::new(16);
+/// let value = Arc::new(json!({"b": 1, "a": 2}));
+/// assert_eq!(cache.to_string(&value).unwrap(), r#"{"a":2,"b":1}"#);
+/// assert_eq!(cache.to_string(&value).unwrap(), r#"{"a":2,"b":1}"#);
+/// assert_eq!(cache.len(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CachedCanonicalizer {
+    canonicalizer: Canonicalizer,
+    capacity: usize,
+    entries: HashMap<usize, (Arc<Value>, String)>,
+    // keys of `entries`, oldest first
+    order: VecDeque<usize>,
+}
+
+impl CachedCanonicalizer {
+    /// Cache of at most `capacity` outputs, serializing with the default options.
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_canonicalizer(Canonicalizer::new(), capacity)
+    }
+
+    /// Cache of at most `capacity` outputs, serializing with the options of
+    /// `canonicalizer`.
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is 0.
+    pub fn with_canonicalizer(canonicalizer: Canonicalizer, capacity: usize) -> Self {
+        assert!(capacity > 0, "the cache needs room for at least one output");
+        CachedCanonicalizer {
+            canonicalizer,
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Canonical form of `value`, computed on the first call for this `Arc`.
+    ///
+    /// Errors are not cached.
+    pub fn to_string(&mut self, value: &Arc<Value>) -> Result<&str, CanonicalJSONError> {
+        let key = Arc::as_ptr(value) as usize;
+        if !self.entries.contains_key(&key) {
+            let serialized = self.canonicalizer.to_string(&**value)?;
+            if self.entries.len() >= self.capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.entries.remove(&oldest);
+                }
+            }
+            self.entries.insert(key, (Arc::clone(value), serialized));
+            self.order.push_back(key);
+        }
+        Ok(&self.entries[&key].1)
+    }
+
+    /// Number of cached outputs.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no output is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forgets every cached output.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CachedCanonicalizer;
+    use crate::ser::to_string;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_cached_canonicalizer() {
+        let mut cache = CachedCanonicalizer::new(2);
+        let value = Arc::new(json!({ "id": "1", "a": [1.5, null] }));
+        let expected = to_string(&*value).unwrap();
+
+        let first = cache.to_string(&value).unwrap().as_ptr();
+        let shared = Arc::clone(&value);
+        let second = cache.to_string(&shared).unwrap();
+        assert_eq!(second, expected);
+        // the very same string: it was not computed again
+        assert_eq!(second.as_ptr(), first);
+        assert_eq!(cache.len(), 1);
+
+        // equal content in another `Arc` is computed again
+        let other = Arc::new((*value).clone());
+        assert_eq!(cache.to_string(&other).unwrap(), expected);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_cached_canonicalizer_eviction() {
+        let mut cache = CachedCanonicalizer::new(2);
+        let values: Vec<_> = (0..3).map(|i| Arc::new(json!([i]))).collect();
+        for value in &values {
+            cache.to_string(value).unwrap();
+        }
+        assert_eq!(cache.len(), 2);
+        assert!(!cache
+            .entries
+            .contains_key(&(Arc::as_ptr(&values[0]) as usize)));
+        assert_eq!(cache.to_string(&values[0]).unwrap(), "[0]");
+        assert!(!cache
+            .entries
+            .contains_key(&(Arc::as_ptr(&values[1]) as usize)));
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
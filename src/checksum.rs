@@ -0,0 +1,65 @@
+//! Non-cryptographic checksums computed over the canonical form.
+//!
+//! Requires the `crc` feature.
+use crate::ser::{to_writer, CanonicalJSONError};
+use serde_json::Value;
+use std::io::{self, Write};
+
+/// CRC-32 (IEEE) of the canonical JSON bytes of `value`
+///
+/// Meant for cheap change detection: unlike a cryptographic digest, it does not
+/// protect against deliberate tampering. The canonical bytes are fed to the
+/// checksum as they are produced, without being collected first.
+///
+/// # Example
+///
+/// ```
+/// use canonical_json::checksum::crc32;
+/// use serde_json::json;
+///
+/// assert_eq!(
+///     crc32(&json!({"b": 1, "a": 2})).unwrap(),
+///     crc32(&json!({"a": 2, "b": 1})).unwrap()
+/// );
+/// ```
+pub fn crc32(value: &Value) -> Result<u32, CanonicalJSONError> {
+    let mut hasher = Crc32(crc32fast::Hasher::new());
+    to_writer(&mut hasher, value)?;
+    Ok(hasher.0.finalize())
+}
+
+/// Feeds the bytes written to a CRC-32 hasher.
+struct Crc32(crc32fast::Hasher);
+
+impl Write for Crc32 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::crc32;
+    use crate::ser::to_string;
+    use serde_json::json;
+
+    #[test]
+    fn test_crc32() {
+        let value = json!({ "id": "1", "tags": ["a", "b"], "meta": { "y": 1, "x": null } });
+        let reordered = json!({ "meta": { "x": null, "y": 1 }, "tags": ["a", "b"], "id": "1" });
+        let changed = json!({ "id": "1", "tags": ["a", "b"], "meta": { "y": 2, "x": null } });
+
+        let checksum = crc32(&value).unwrap();
+        assert_eq!(
+            checksum,
+            crc32fast::hash(to_string(&value).unwrap().as_bytes())
+        );
+        assert_eq!(crc32(&reordered).unwrap(), checksum);
+        assert_ne!(crc32(&changed).unwrap(), checksum);
+    }
+}
@@ -0,0 +1,213 @@
+//! Structural comparison of JSON values.
+use crate::path::key_segment;
+use crate::ser::{cmp_utf16, to_string, to_vec, to_writer, CanonicalJSONError};
+use serde_json::{Map, Value};
+use std::io::{self, Write};
+
+/// Object members that differ between two values, by path.
+///
+/// Paths use the syntax of [crate::path::canonicalize_matching] and are listed
+/// in canonical order. Arrays and scalars are compared as a whole: a value that
+/// is an object on both sides is compared member by member, anything else is
+/// reported as changed when its canonical forms differ.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StructuralDiff {
+    /// Members only present in the new value.
+    pub added: Vec<String>,
+    /// Members only present in the old value.
+    pub removed: Vec<String>,
+    /// Values whose canonical form changed.
+    pub changed: Vec<String>,
+}
+
+impl StructuralDiff {
+    /// Whether both values have the same canonical form.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare two JSON values through their canonical forms
+///
+/// Key order never counts as a difference, and neither does the way numbers
+/// were written (`1.50` and `1.5` are the same canonical number).
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::diff::canonical_structural_diff;
+/// # use serde_json::json;
+/// let old = json!({"name": "a", "limits": {"cpu": 1, "memory": 2}});
+/// let new = json!({"limits": {"memory": 3, "cpu": 1.0e0}, "owner": "b"});
+/// let diff = canonical_structural_diff(&old, &new);
+/// assert_eq!(diff.added, vec!["$.owner"]);
+/// assert_eq!(diff.removed, vec!["$.name"]);
+/// assert_eq!(diff.changed, vec!["$.limits.cpu", "$.limits.memory"]);
+/// ```
+pub fn canonical_structural_diff(old: &Value, new: &Value) -> StructuralDiff {
+    let mut diff = StructuralDiff::default();
+    compare(old, new, "$", &mut diff);
+    diff
+}
+
+/// Whether two JSON values have the same canonical form
+///
+/// Only `a` is serialized to memory: the canonical bytes of `b` are compared
+/// with it as they are produced, stopping at the first difference.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::diff::canonical_eq;
+/// # use serde_json::json;
+/// let a = json!({"b": [1.5, true], "a": null});
+/// let b: serde_json::Value = serde_json::from_str(r#"{"a":null,"b":[15e-1,true]}"#).unwrap();
+/// assert!(canonical_eq(&a, &b).unwrap());
+/// assert!(!canonical_eq(&a, &json!({"a": null})).unwrap());
+/// ```
+pub fn canonical_eq(a: &Value, b: &Value) -> Result<bool, CanonicalJSONError> {
+    let expected = to_vec(a)?;
+    let mut comparison = Comparison {
+        expected: &expected,
+        differs: false,
+    };
+    match to_writer(&mut comparison, b) {
+        Ok(()) => Ok(comparison.expected.is_empty()),
+        Err(_) if comparison.differs => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Checks that what is written matches `expected`, failing at the first byte
+/// that does not.
+struct Comparison<'a> {
+    // what is left to be written
+    expected: &'a [u8],
+    differs: bool,
+}
+
+impl Write for Comparison<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.expected.strip_prefix(buf) {
+            Some(rest) => self.expected = rest,
+            None => {
+                self.differs = true;
+                return Err(io::Error::other("canonical forms differ"));
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn compare(old: &Value, new: &Value, path: &str, diff: &mut StructuralDiff) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => compare_members(old, new, path, diff),
+        _ => {
+            if canonical(old) != canonical(new) {
+                diff.changed.push(path.to_string());
+            }
+        }
+    }
+}
+
+fn compare_members(
+    old: &Map<String, Value>,
+    new: &Map<String, Value>,
+    path: &str,
+    diff: &mut StructuralDiff,
+) {
+    // in canonical order, whatever the iteration order of the maps
+    let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+    keys.sort_by(|a, b| cmp_utf16(a.as_bytes(), b.as_bytes()));
+    keys.dedup();
+    for key in keys {
+        let path = format!("{}{}", path, segment(key));
+        match (old.get(key), new.get(key)) {
+            (Some(old), Some(new)) => compare(old, new, &path, diff),
+            (Some(_), None) => diff.removed.push(path),
+            (None, Some(_)) => diff.added.push(path),
+            (None, None) => unreachable!("key {:?} comes from either map", key),
+        }
+    }
+}
+
+fn canonical(value: &Value) -> String {
+    // the default options cannot fail on a `Value`: its numbers are finite
+    // and its keys are unique
+    to_string(value).expect("a Value always has a canonical form")
+}
+
+fn segment(key: &str) -> String {
+    key_segment(key).expect("a key always has a canonical form")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical_eq, canonical_structural_diff, StructuralDiff};
+    use serde_json::json;
+
+    #[test]
+    fn test_structural_diff_nested() {
+        let old = json!({
+            "kept": { "a": [1, 2], "b": { "c": true } },
+            "gone": { "x": 1 },
+            "deep": { "z": { "y": "old", "removed": null } },
+            "swapped": [{ "a": 1 }],
+        });
+        let new = json!({
+            "kept": { "b": { "c": true }, "a": [1.0e0, 2.00] },
+            "deep": { "z": { "y": "new", "added": [] } },
+            "swapped": { "a": 1 },
+            "a.b": 1,
+        });
+        let diff = canonical_structural_diff(&old, &new);
+        assert_eq!(
+            diff,
+            StructuralDiff {
+                added: vec![r#"$["a.b"]"#.to_string(), "$.deep.z.added".to_string()],
+                removed: vec!["$.deep.z.removed".to_string(), "$.gone".to_string()],
+                changed: vec![
+                    "$.deep.z.y".to_string(),
+                    "$.kept.a".to_string(),
+                    "$.swapped".to_string()
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_canonical_eq() {
+        let a = json!({ "id": "1", "tags": ["x", "é"], "n": { "b": 1e3, "a": -0.5 } });
+        let b: serde_json::Value =
+            serde_json::from_str(r#"{"n":{"a":-5e-1,"b":1000.0},"tags":["x","\u00e9"],"id":"1"}"#)
+                .unwrap();
+        assert!(canonical_eq(&a, &b).unwrap());
+        assert!(canonical_eq(&b, &a).unwrap());
+
+        // prefixes, extensions and changes in the middle all differ
+        for other in &[
+            json!({ "id": "1" }),
+            json!({ "id": "1", "tags": ["x", "é"], "n": { "b": 1e3, "a": -0.5 }, "z": 0 }),
+            json!({ "id": "2", "tags": ["x", "é"], "n": { "b": 1e3, "a": -0.5 } }),
+            json!({ "id": "1", "tags": ["x", "é"], "n": { "b": 1000, "a": -0.5 } }),
+        ] {
+            assert!(!canonical_eq(&a, other).unwrap(), "{}", other);
+            assert!(!canonical_eq(other, &a).unwrap(), "{}", other);
+        }
+        assert!(canonical_eq(&json!(null), &json!(null)).unwrap());
+    }
+
+    #[test]
+    fn test_structural_diff_same_canonical_form() {
+        let old = json!({ "b": { "d": 1.5, "c": "é" }, "a": [null, {}] });
+        let new: serde_json::Value =
+            serde_json::from_str(r#"{"a":[null,{}],"b":{"c":"é","d":15e-1}}"#).unwrap();
+        assert!(canonical_structural_diff(&old, &new).is_empty());
+        assert_eq!(
+            canonical_structural_diff(&json!(1), &json!(2)).changed,
+            vec!["$"]
+        );
+    }
+}
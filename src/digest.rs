@@ -0,0 +1,198 @@
+//! Content hashes computed over the canonical form.
+//!
+//! Requires the `digest` feature.
+use crate::ser::{cmp_utf16, to_vec, to_writer, CanonicalJSONError};
+use serde::ser::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Computes digests over the canonical JSON representation of a value.
+///
+/// A blanket implementation is provided for every [serde::Serialize] type, so
+/// structs deriving `Serialize` get their canonical digest for free.
+///
+/// # Example
+///
+/// ```
+/// use canonical_json::CanonicalHash;
+/// use serde_json::json;
+///
+/// let a = json!({"b": 1, "a": 2}).canonical_sha256().unwrap();
+/// let b = json!({"a": 2, "b": 1}).canonical_sha256().unwrap();
+/// assert_eq!(a, b);
+/// ```
+pub trait CanonicalHash {
+    /// SHA-256 of the canonical JSON bytes.
+    fn canonical_sha256(&self) -> Result<[u8; 32], CanonicalJSONError>;
+}
+
+impl<T: ?Sized + Serialize> CanonicalHash for T {
+    fn canonical_sha256(&self) -> Result<[u8; 32], CanonicalJSONError> {
+        let mut hasher = Sha256::new();
+        to_writer(&mut hasher, self)?;
+        Ok(hasher.finalize().into())
+    }
+}
+
+/// SHA-256 of the canonical form of a JSON value
+///
+/// The canonical bytes are written straight into the hasher as they are
+/// produced, rather than collected first. Callers encode the raw digest as
+/// they see fit.
+///
+/// # Example
+///
+/// ```
+/// use canonical_json::digest::sha256;
+/// use serde_json::json;
+///
+/// let digest = sha256(&json!({"b": 1, "a": 2})).unwrap();
+/// assert_eq!(digest[..4], [0xd3, 0x62, 0x6a, 0xc3]);
+/// ```
+pub fn sha256(input: &Value) -> Result<[u8; 32], CanonicalJSONError> {
+    input.canonical_sha256()
+}
+
+/// Root of a Merkle tree over the canonical form of a JSON value
+///
+/// Every node is hashed with SHA-256, its first byte telling what it is:
+///
+/// - a scalar (`null`, boolean, number or string) hashes as `0x00` followed
+///   by its canonical JSON, e.g. `0x00 "é"`;
+/// - an array hashes as `0x01` followed by the hashes of its elements, in order;
+/// - an object hashes as `0x02` followed by the hashes of its members, in the
+///   canonical order of their keys, a member hashing as `0x03` followed by the
+///   canonical JSON of its key and the hash of its value.
+///
+/// The root therefore does not depend on the order of object members, and a
+/// subtree can be disclosed along with the hashes of its siblings only.
+///
+/// # Example
+///
+/// ```
+/// use canonical_json::digest::merkle_root_sha256;
+/// use serde_json::json;
+///
+/// let a = merkle_root_sha256(&json!({"b": [1, 2], "a": null})).unwrap();
+/// let b = merkle_root_sha256(&json!({"a": null, "b": [1, 2]})).unwrap();
+/// assert_eq!(a, b);
+/// ```
+pub fn merkle_root_sha256(value: &Value) -> Result<[u8; 32], CanonicalJSONError> {
+    let mut hasher = Sha256::new();
+    match value {
+        Value::Array(values) => {
+            hasher.update([0x01]);
+            for value in values {
+                hasher.update(merkle_root_sha256(value)?);
+            }
+        }
+        Value::Object(map) => {
+            hasher.update([0x02]);
+            let mut members: Vec<_> = map.iter().collect();
+            members.sort_by(|a, b| cmp_utf16(a.0.as_bytes(), b.0.as_bytes()));
+            for (key, value) in members {
+                let mut member = Sha256::new();
+                member.update([0x03]);
+                member.update(to_vec(&Value::from(key.as_str()))?);
+                member.update(merkle_root_sha256(value)?);
+                hasher.update(member.finalize());
+            }
+        }
+        scalar => {
+            hasher.update([0x00]);
+            hasher.update(to_vec(scalar)?);
+        }
+    }
+    Ok(hasher.finalize().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merkle_root_sha256, sha256, CanonicalHash};
+    use crate::ser::to_string;
+    use serde::Serialize;
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+
+    #[derive(Serialize)]
+    struct Record {
+        last_modified: u64,
+        id: String,
+        title: String,
+    }
+
+    #[test]
+    fn test_canonical_sha256_of_derived_struct() {
+        let record = Record {
+            last_modified: 12345,
+            id: "1".to_string(),
+            title: "I ❤ testing".to_string(),
+        };
+        let value = json!({ "last_modified": 12345, "id": "1", "title": "I ❤ testing" });
+        let expected: [u8; 32] = Sha256::digest(to_string(&value).unwrap().as_bytes()).into();
+
+        assert_eq!(record.canonical_sha256().unwrap(), expected);
+        assert_eq!(value.canonical_sha256().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_sha256() {
+        let value = json!({ "b": [1.5, "é"], "a": { "d": null, "c": true } });
+        let expected: [u8; 32] = Sha256::digest(to_string(&value).unwrap().as_bytes()).into();
+        assert_eq!(sha256(&value).unwrap(), expected);
+        assert_eq!(
+            sha256(&json!("")).unwrap(),
+            <[u8; 32]>::from(Sha256::digest(b"\"\""))
+        );
+    }
+
+    #[test]
+    fn test_merkle_root_ignores_member_order() {
+        let a = json!({ "id": "1", "tags": ["a", "b"], "meta": { "y": 1, "x": null } });
+        let b = json!({ "meta": { "x": null, "y": 1 }, "tags": ["a", "b"], "id": "1" });
+        assert_eq!(
+            merkle_root_sha256(&a).unwrap(),
+            merkle_root_sha256(&b).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_merkle_root_changes_with_leaves() {
+        let root = merkle_root_sha256(&json!({ "tags": ["a", "b"], "n": 1 })).unwrap();
+        for changed in &[
+            json!({ "tags": ["a", "c"], "n": 1 }),
+            json!({ "tags": ["b", "a"], "n": 1 }),
+            json!({ "tags": ["a", "b"], "n": 2 }),
+            json!({ "tags": ["a", "b"], "m": 1 }),
+            json!({ "tags": ["a", "b"], "n": 1, "o": null }),
+        ] {
+            assert_ne!(merkle_root_sha256(changed).unwrap(), root, "{}", changed);
+        }
+    }
+
+    #[test]
+    fn test_merkle_root_scheme() {
+        let leaf = |bytes: &[u8]| -> [u8; 32] { Sha256::digest([&[0x00], bytes].concat()).into() };
+        assert_eq!(
+            merkle_root_sha256(&json!("é")).unwrap(),
+            leaf("\"é\"".as_bytes())
+        );
+
+        let array: [u8; 32] =
+            Sha256::digest([&[0x01][..], &leaf(b"1"), &leaf(b"null")].concat()).into();
+        assert_eq!(merkle_root_sha256(&json!([1, null])).unwrap(), array);
+
+        let member: [u8; 32] = Sha256::digest([&[0x03][..], br#""a""#, &array].concat()).into();
+        let object: [u8; 32] = Sha256::digest([&[0x02][..], &member].concat()).into();
+        assert_eq!(
+            merkle_root_sha256(&json!({ "a": [1, null] })).unwrap(),
+            object
+        );
+
+        // an array and its string form do not collide
+        assert_ne!(
+            merkle_root_sha256(&json!([])).unwrap(),
+            merkle_root_sha256(&json!("[]")).unwrap()
+        );
+    }
+}
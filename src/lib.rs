@@ -1,6 +1,37 @@
 //! Canonical JSON library to serialize JSON values to String
 //!
+mod borrowed;
+pub mod buf;
+pub mod cache;
+#[cfg(feature = "crc")]
+pub mod checksum;
+pub mod diff;
+#[cfg(feature = "digest")]
+pub mod digest;
+mod non_finite;
+pub mod path;
+pub mod redact;
 pub mod ser;
+pub mod sink;
+pub mod stream;
+pub use buf::CanonicalBuf;
+#[cfg(feature = "digest")]
+pub use digest::CanonicalHash;
+pub use ser::canonicalize;
+pub use ser::canonicalize_slice;
+pub use ser::from_str;
+pub use ser::to_fixed_buf;
 pub use ser::to_string;
+pub use ser::to_vec;
+pub use ser::to_writer;
+pub use ser::CanonicalFormatter;
 pub use ser::CanonicalJSONError;
+pub use ser::Canonicalizer;
+pub use ser::DuplicateKeyPolicy;
+#[allow(deprecated)]
 pub use ser::JsonFormatter;
+pub use ser::NfKind;
+pub use ser::NonFinite;
+pub use ser::NumberFormat;
+pub use ser::RoundingMode;
+pub use ser::Stats;
@@ -0,0 +1,365 @@
+//! Count of the non-finite numbers a `Serialize` implementation emits.
+//!
+//! `serde_json` writes `NaN` and infinities as `null` without handing them to
+//! the formatter, so they can only be seen from a serializer of our own.
+#[cfg(feature = "arbitrary_precision")]
+use crate::borrowed::NUMBER_TOKEN;
+use serde::ser::{self, Serialize};
+
+/// Serializer discarding everything but the non-finite floats it is handed.
+#[derive(Default)]
+struct NonFiniteCounter {
+    count: usize,
+    first: Option<f64>,
+    // within a number kept as text by `arbitrary_precision`
+    number: bool,
+    // arrays and objects entered, as the formatter counts them
+    depth: usize,
+    max_depth: Option<usize>,
+}
+
+impl NonFiniteCounter {
+    // what lies deeper than `max_depth` is not looked through: serializing
+    // it fails anyway, and a recursive `Serialize` would never end
+    fn too_deep(&self) -> bool {
+        self.max_depth.is_some_and(|limit| self.depth > limit)
+    }
+
+    fn element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), serde_json::Error> {
+        if self.too_deep() {
+            return Ok(());
+        }
+        value.serialize(self)
+    }
+}
+
+/// Number of `NaN` and infinite `f32` and `f64` emitted by `value`, and of
+/// numbers too large for an `f64` with `arbitrary_precision`.
+pub(crate) fn count_non_finite<V>(value: &V) -> Result<usize, serde_json::Error>
+where
+    V: ?Sized + Serialize,
+{
+    let mut counter = NonFiniteCounter::default();
+    value.serialize(&mut counter)?;
+    Ok(counter.count)
+}
+
+/// First `NaN` or infinite `f32` or `f64` emitted by `value`, or number too
+/// large for an `f64`, if any, among the arrays and objects nested at most
+/// `max_depth` levels deep.
+pub(crate) fn first_non_finite<V>(
+    value: &V,
+    max_depth: Option<usize>,
+) -> Result<Option<f64>, serde_json::Error>
+where
+    V: ?Sized + Serialize,
+{
+    let mut counter = NonFiniteCounter {
+        max_depth,
+        ..NonFiniteCounter::default()
+    };
+    value.serialize(&mut counter)?;
+    Ok(counter.first)
+}
+
+impl ser::Serializer for &mut NonFiniteCounter {
+    type Ok = ();
+    type Error = serde_json::Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, _value: bool) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_i64(self, _value: i64) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_i128(self, _value: i128) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_u64(self, _value: u64) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_u128(self, _value: u128) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_i8(self, _value: i8) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_i16(self, _value: i16) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_i32(self, _value: i32) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_u8(self, _value: u8) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_u16(self, _value: u16) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_u32(self, _value: u32) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<(), Self::Error> {
+        self.serialize_f64(f64::from(value))
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<(), Self::Error> {
+        if !value.is_finite() {
+            self.count += 1;
+            self.first = self.first.or(Some(value));
+        }
+        Ok(())
+    }
+
+    fn serialize_char(self, _value: char) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_str(self, value: &str) -> Result<(), Self::Error> {
+        if self.number {
+            if let Ok(number) = value.parse() {
+                return self.serialize_f64(number);
+            }
+        }
+        Ok(())
+    }
+
+    fn serialize_bytes(self, _value: &[u8]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        // written as an object
+        self.depth += 1;
+        self.element(value)?;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self, Self::Error> {
+        self.depth += 1;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self, Self::Error> {
+        self.depth += 1;
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self, Self::Error> {
+        self.depth += 1;
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, Self::Error> {
+        // an array within an object
+        self.depth += 2;
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self, Self::Error> {
+        self.depth += 1;
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, Self::Error> {
+        #[cfg(feature = "arbitrary_precision")]
+        {
+            self.number = _name == NUMBER_TOKEN;
+        }
+        if !self.number {
+            self.depth += 1;
+        }
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, Self::Error> {
+        // an object within an object
+        self.depth += 2;
+        Ok(self)
+    }
+}
+
+impl ser::SerializeSeq for &mut NonFiniteCounter {
+    type Ok = ();
+    type Error = serde_json::Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        self.depth -= 1;
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut NonFiniteCounter {
+    type Ok = ();
+    type Error = serde_json::Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        self.depth -= 1;
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut NonFiniteCounter {
+    type Ok = ();
+    type Error = serde_json::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        self.depth -= 1;
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for &mut NonFiniteCounter {
+    type Ok = ();
+    type Error = serde_json::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        self.depth -= 2;
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for &mut NonFiniteCounter {
+    type Ok = ();
+    type Error = serde_json::Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Self::Error> {
+        // a non-finite float key is an error for `serde_json`, not a `null`
+        key.serialize(&mut NonFiniteCounter::default())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        self.depth -= 1;
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut NonFiniteCounter {
+    type Ok = ();
+    type Error = serde_json::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        if self.number {
+            self.number = false;
+        } else {
+            self.depth -= 1;
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut NonFiniteCounter {
+    type Ok = ();
+    type Error = serde_json::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        self.depth -= 2;
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+//! Path expressions over canonical JSON values.
+use crate::ser::{cmp_utf16, to_string, CanonicalFormatter, CanonicalJSONError, Canonicalizer};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+/// One step of a path expression.
+#[derive(Debug, PartialEq)]
+enum Step<'a> {
+    Field(Cow<'a, str>),
+    Index(usize),
+    Wildcard,
+}
+
+fn parse(path: &str) -> Result<Vec<Step<'_>>, CanonicalJSONError> {
+    let invalid = || CanonicalJSONError::InvalidPath(path.to_string());
+    let mut rest = path.strip_prefix('$').ok_or_else(invalid)?;
+    let mut steps = vec![];
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            let (name, after) = after.split_at(end);
+            steps.push(match name {
+                "" => return Err(invalid()),
+                "*" => Step::Wildcard,
+                name => Step::Field(name.into()),
+            });
+            rest = after;
+        } else if let Some(after) = rest
+            .strip_prefix('[')
+            .filter(|after| after.starts_with('"'))
+        {
+            // a JSON string, which may hold `]` or escapes of its own
+            let mut names = serde_json::Deserializer::from_str(after).into_iter::<String>();
+            let name = match names.next() {
+                Some(Ok(name)) => name,
+                _ => return Err(invalid()),
+            };
+            steps.push(Step::Field(name.into()));
+            rest = after[names.byte_offset()..]
+                .strip_prefix(']')
+                .ok_or_else(invalid)?;
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(invalid)?;
+            steps.push(match &after[..end] {
+                "*" => Step::Wildcard,
+                index => Step::Index(index.parse().map_err(|_| invalid())?),
+            });
+            rest = &after[end + 1..];
+        } else {
+            return Err(invalid());
+        }
+    }
+    Ok(steps)
+}
+
+fn select<'v>(value: &'v Value, steps: &[Step], matches: &mut Vec<&'v Value>) {
+    let (step, rest) = match steps.split_first() {
+        Some(split) => split,
+        None => return matches.push(value),
+    };
+    match (step, value) {
+        (Step::Field(name), Value::Object(map)) => {
+            if let Some(child) = map.get(name.as_ref()) {
+                select(child, rest, matches);
+            }
+        }
+        (Step::Index(index), Value::Array(values)) => {
+            if let Some(child) = values.get(*index) {
+                select(child, rest, matches);
+            }
+        }
+        (Step::Wildcard, Value::Array(values)) => {
+            for child in values {
+                select(child, rest, matches);
+            }
+        }
+        (Step::Wildcard, Value::Object(map)) => {
+            // in canonical order, whatever the iteration order of `map`
+            let mut members: Vec<_> = map.iter().collect();
+            members.sort_by(|a, b| cmp_utf16(a.0.as_bytes(), b.0.as_bytes()));
+            for (_, child) in members {
+                select(child, rest, matches);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Canonicalize every value matched by a path expression
+///
+/// Paths are a minimal subset of JSONPath: they start with `$` (the value
+/// itself), followed by any number of object fields (`.name`, or a JSON
+/// string in brackets such as `["a.b"]` for any other key), array indices
+/// (`[0]`) and wildcards (`.*` or `[*]`, every member of an object or element
+/// of an array). Matches are returned in document order, objects being visited
+/// in key order; a path that matches nothing gives an empty result.
+///
+/// Fails with [CanonicalJSONError::InvalidPath] if `path` cannot be parsed.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::path::canonicalize_matching;
+/// # use serde_json::json;
+/// let value = json!({"items": [{"id": "1", "n": 1}, {"id": "2"}]});
+/// assert_eq!(
+///     canonicalize_matching(&value, "$.items[*].id").unwrap(),
+///     vec![r#""1""#, r#""2""#]
+/// );
+/// ```
+pub fn canonicalize_matching(value: &Value, path: &str) -> Result<Vec<String>, CanonicalJSONError> {
+    let steps = parse(path)?;
+    let mut matches = vec![];
+    select(value, &steps, &mut matches);
+    let canonicalizer = Canonicalizer::new();
+    matches
+        .into_iter()
+        .map(|value| canonicalizer.to_string(value))
+        .collect()
+}
+
+/// Serialize a JSON value to String, reporting every object key on the way
+///
+/// `callback` is called with the path of the object and the key, in the order
+/// keys appear in the canonical output. Paths use the syntax of
+/// [canonicalize_matching], keys other than ASCII letters, digits and
+/// underscores being written as a quoted canonical string instead (`$["a.b"]`).
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::path::to_string_with_key_callback;
+/// # use serde_json::json;
+/// let mut keys = vec![];
+/// let value = json!({"items": [{"id": 1}], "a": null});
+/// let serialized = to_string_with_key_callback(&value, &mut |path: &str, key: &str| {
+///     keys.push(format!("{} {}", path, key))
+/// })
+/// .unwrap();
+/// assert_eq!(serialized, r#"{"a":null,"items":[{"id":1}]}"#);
+/// assert_eq!(keys, vec!["$ a", "$ items", "$.items[0] id"]);
+/// ```
+pub fn to_string_with_key_callback<F>(
+    value: &Value,
+    callback: F,
+) -> Result<String, CanonicalJSONError>
+where
+    F: FnMut(&str, &str),
+{
+    let callback = RefCell::new(callback);
+    let tracked = Tracked {
+        value,
+        path: "$".to_string(),
+        callback: &callback,
+    };
+    let mut serializer = serde_json::Serializer::with_formatter(vec![], CanonicalFormatter::new());
+    tracked.serialize(&mut serializer)?;
+    Ok(String::from_utf8(serializer.into_inner())?)
+}
+
+/// A value along with its path, serialized members first reported to `callback`.
+struct Tracked<'a, F> {
+    value: &'a Value,
+    path: String,
+    callback: &'a RefCell<F>,
+}
+
+impl<'a, F> Tracked<'a, F> {
+    fn child(&self, value: &'a Value, segment: &str) -> Self {
+        Tracked {
+            value,
+            path: format!("{}{}", self.path, segment),
+            callback: self.callback,
+        }
+    }
+}
+
+pub(crate) fn key_segment(key: &str) -> Result<String, CanonicalJSONError> {
+    let simple = !key.is_empty()
+        && key
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_');
+    Ok(if simple {
+        format!(".{}", key)
+    } else {
+        format!("[{}]", to_string(&Value::from(key))?)
+    })
+}
+
+impl<F: FnMut(&str, &str)> Serialize for Tracked<'_, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.value {
+            Value::Object(map) => {
+                // in canonical order, whatever the iteration order of `map`
+                let mut members: Vec<_> = map.iter().collect();
+                members.sort_by(|a, b| cmp_utf16(a.0.as_bytes(), b.0.as_bytes()));
+                let mut serializer = serializer.serialize_map(Some(members.len()))?;
+                for (key, value) in members {
+                    (self.callback.borrow_mut())(&self.path, key);
+                    let segment = key_segment(key).map_err(serde::ser::Error::custom)?;
+                    serializer.serialize_entry(key, &self.child(value, &segment))?;
+                }
+                serializer.end()
+            }
+            Value::Array(values) => {
+                let mut serializer = serializer.serialize_seq(Some(values.len()))?;
+                for (index, value) in values.iter().enumerate() {
+                    serializer.serialize_element(&self.child(value, &format!("[{}]", index)))?;
+                }
+                serializer.end()
+            }
+            scalar => scalar.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonicalize_matching, key_segment, parse, to_string_with_key_callback, Step};
+    use crate::diff::canonical_structural_diff;
+    use crate::ser::CanonicalJSONError;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("$").unwrap(), vec![]);
+        assert_eq!(
+            parse("$.items[2].*[*]").unwrap(),
+            vec![
+                Step::Field("items".into()),
+                Step::Index(2),
+                Step::Wildcard,
+                Step::Wildcard
+            ]
+        );
+        assert_eq!(
+            parse(r#"$["a.b"][" ]\"\u00e9"]["*"][0]"#).unwrap(),
+            vec![
+                Step::Field("a.b".into()),
+                Step::Field(" ]\"\u{e9}".into()),
+                Step::Field("*".into()),
+                Step::Index(0)
+            ]
+        );
+        for path in &[
+            "",
+            "items",
+            "$.",
+            "$..a",
+            "$[",
+            "$[-1]",
+            "$[a]",
+            "$a",
+            r#"$["a""#,
+            r#"$["a"b]"#,
+            r#"$["\x"]"#,
+        ] {
+            assert!(
+                matches!(parse(path), Err(CanonicalJSONError::InvalidPath(_))),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_matching_wildcard() {
+        let value = json!({
+            "items": [
+                { "id": { "b": 1, "a": 2 } },
+                { "name": "no id" },
+                { "id": [1.5] },
+            ],
+        });
+        assert_eq!(
+            canonicalize_matching(&value, "$.items[*].id").unwrap(),
+            vec![r#"{"a":2,"b":1}"#, "[1.5E0]"]
+        );
+        assert_eq!(
+            canonicalize_matching(&value, "$.items[0].id.*").unwrap(),
+            vec!["2", "1"]
+        );
+        assert_eq!(
+            canonicalize_matching(&value, "$").unwrap(),
+            vec![r#"{"items":[{"id":{"a":2,"b":1}},{"name":"no id"},{"id":[1.5E0]}]}"#]
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_matching_wildcard_key_order() {
+        // U+E000 sorts after U+1F600 in UTF-16, before it in UTF-8
+        let value = json!({ "\u{e000}": 1, "\u{1f600}": 2, "a": 3 });
+        assert_eq!(
+            canonicalize_matching(&value, "$.*").unwrap(),
+            vec!["3", "2", "1"]
+        );
+        assert_eq!(
+            crate::ser::to_string(&value).unwrap(),
+            "{\"a\":3,\"\u{1f600}\":2,\"\u{e000}\":1}"
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_matching_missing_path() {
+        let value = json!({ "items": [{ "id": 1 }] });
+        for path in &[
+            "$.missing",
+            "$.items[1].id",
+            "$.items[*].missing",
+            "$.items.id",
+        ] {
+            assert!(
+                canonicalize_matching(&value, path).unwrap().is_empty(),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_string_with_key_callback() {
+        let value = json!({
+            "z": { "b": [{ "y": 1, "x": 2 }, 3], "a": null },
+            "items": [[{ "id": "1" }]],
+            "a.b": { "": {} },
+        });
+        let mut keys = vec![];
+        let serialized = to_string_with_key_callback(&value, |path: &str, key: &str| {
+            keys.push((path.to_string(), key.to_string()))
+        })
+        .unwrap();
+
+        assert_eq!(serialized, crate::ser::to_string(&value).unwrap());
+        let expected = [
+            ("$", "a.b"),
+            (r#"$["a.b"]"#, ""),
+            ("$", "items"),
+            ("$.items[0][0]", "id"),
+            ("$", "z"),
+            ("$.z", "a"),
+            ("$.z", "b"),
+            ("$.z.b[0]", "x"),
+            ("$.z.b[0]", "y"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(path, key)| (path.to_string(), key.to_string()))
+            .collect();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn test_reported_paths_round_trip() {
+        let value = json!({ "a.b": { "": 1, "c]\"": 2 }, "x": [{ "é": 3 }] });
+        let mut paths = vec![];
+        to_string_with_key_callback(&value, |path: &str, key: &str| {
+            paths.push(format!("{}{}", path, key_segment(key).unwrap()))
+        })
+        .unwrap();
+        let diff = canonical_structural_diff(&json!({}), &value);
+        assert_eq!(diff.added, [r#"$["a.b"]"#, "$.x"]);
+        paths.extend(diff.added);
+
+        for path in &paths {
+            assert_eq!(
+                canonicalize_matching(&value, path).unwrap().len(),
+                1,
+                "{}",
+                path
+            );
+        }
+        assert_eq!(
+            canonicalize_matching(&value, r#"$["a.b"]["c]\""]"#).unwrap(),
+            vec!["2"]
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Canonicalization hiding the values of sensitive keys.
+use crate::ser::{to_string, CanonicalJSONError};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Placeholder written by [Redaction::Replace].
+pub const REDACTED: &str = "[REDACTED]";
+
+/// What happens to object members whose key is redacted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Redaction {
+    /// Keep the key, with the string [REDACTED] as its value.
+    #[default]
+    Replace,
+    /// Drop the member altogether.
+    Remove,
+}
+
+/// Serialize a JSON value to String, redacting the members of its objects
+/// whose key is in `keys`
+///
+/// Objects are searched at any depth, inside arrays too. The value of a
+/// redacted member is not looked into, whatever it is.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::redact::{canonicalize_redacting, Redaction};
+/// # use serde_json::json;
+/// # use std::collections::HashSet;
+/// let keys: HashSet<String> = vec!["password".to_string()].into_iter().collect();
+/// let value = json!({"user": {"password": "hunter2", "name": "a"}});
+/// assert_eq!(
+///     canonicalize_redacting(&value, &keys, Redaction::Replace).unwrap(),
+///     r#"{"user":{"name":"a","password":"[REDACTED]"}}"#
+/// );
+/// assert_eq!(
+///     canonicalize_redacting(&value, &keys, Redaction::Remove).unwrap(),
+///     r#"{"user":{"name":"a"}}"#
+/// );
+/// ```
+pub fn canonicalize_redacting(
+    value: &Value,
+    keys: &HashSet<String>,
+    redaction: Redaction,
+) -> Result<String, CanonicalJSONError> {
+    to_string(&Redacted {
+        value,
+        keys,
+        redaction,
+    })
+}
+
+/// A value serialized with the members of `keys` redacted.
+struct Redacted<'a> {
+    value: &'a Value,
+    keys: &'a HashSet<String>,
+    redaction: Redaction,
+}
+
+impl<'a> Redacted<'a> {
+    fn child(&self, value: &'a Value) -> Self {
+        Redacted { value, ..*self }
+    }
+}
+
+impl Serialize for Redacted<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.value {
+            Value::Object(map) => {
+                let mut serializer = serializer.serialize_map(None)?;
+                for (key, value) in map {
+                    match (self.keys.contains(key), self.redaction) {
+                        (false, _) => serializer.serialize_entry(key, &self.child(value))?,
+                        (true, Redaction::Replace) => serializer.serialize_entry(key, REDACTED)?,
+                        (true, Redaction::Remove) => {}
+                    }
+                }
+                serializer.end()
+            }
+            Value::Array(values) => {
+                let mut serializer = serializer.serialize_seq(Some(values.len()))?;
+                for value in values {
+                    serializer.serialize_element(&self.child(value))?;
+                }
+                serializer.end()
+            }
+            scalar => scalar.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonicalize_redacting, Redaction};
+    use serde_json::json;
+    use std::collections::HashSet;
+
+    fn keys(keys: &[&str]) -> HashSet<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    #[test]
+    fn test_redact_nested_password() {
+        let value = json!({
+            "users": [
+                { "name": "a", "password": "hunter2" },
+                { "name": "b", "auth": { "password": { "hash": "x" }, "otp": 123 } },
+            ],
+            "password": null,
+        });
+        let keys = keys(&["password", "otp"]);
+
+        assert_eq!(
+            canonicalize_redacting(&value, &keys, Redaction::Replace).unwrap(),
+            r#"{"password":"[REDACTED]","users":[{"name":"a","password":"[REDACTED]"},{"auth":{"otp":"[REDACTED]","password":"[REDACTED]"},"name":"b"}]}"#
+        );
+        assert_eq!(
+            canonicalize_redacting(&value, &keys, Redaction::Remove).unwrap(),
+            r#"{"users":[{"name":"a"},{"auth":{},"name":"b"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_redact_nothing() {
+        let value = json!({ "b": ["password"], "a": { "Password": 1 } });
+        assert_eq!(
+            canonicalize_redacting(&value, &keys(&["password"]), Redaction::Remove).unwrap(),
+            crate::ser::to_string(&value).unwrap()
+        );
+        assert_eq!(
+            canonicalize_redacting(&value, &HashSet::new(), Redaction::Replace).unwrap(),
+            r#"{"a":{"Password":1},"b":["password"]}"#
+        );
+    }
+}
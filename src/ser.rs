@@ -1,382 +1,2534 @@
-use regex::Regex;
+use crate::borrowed::Borrowed;
+use crate::non_finite::{count_non_finite, first_non_finite};
+use serde::de::Deserialize;
 use serde::ser::Serialize;
-use serde_json::ser::{CharEscape, Formatter};
-use std::io::Write;
+use serde_json::ser::{CharEscape, CompactFormatter, Formatter};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
 use std::string::FromUtf8Error as Utf8Error;
 use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
 
 /// Implements the [serde_json::ser::Formatter] trait for serializing [serde_json::Value] objects into their
 /// canonical string representation.
 ///
+/// Object members are buffered until the object is complete, and then written
+/// ordered by key, whatever order they were serialized in.
+///
+/// Use [Canonicalizer::formatter] to obtain a formatter with non-default options.
+///
 /// # Example
 ///
 /// ```
 /// use serde::Serialize;
 /// use serde_json::json;
-/// use canonical_json::JsonFormatter;
+/// use canonical_json::CanonicalFormatter;
 ///
 /// let input = json!(vec!["one", "two", "three"]);
 /// let mut bytes = vec![];
-/// let mut serializer = serde_json::Serializer::with_formatter(&mut bytes, JsonFormatter);
+/// let mut serializer = serde_json::Serializer::with_formatter(&mut bytes, CanonicalFormatter::new());
 /// input.serialize(&mut serializer).unwrap();
 ///
 /// assert_eq!(String::from_utf8(bytes).unwrap(), r#"["one","two","three"]"#);
 /// ```
-pub struct JsonFormatter;
+#[derive(Debug, Clone, Default)]
+pub struct CanonicalFormatter {
+    options: Canonicalizer,
+    depth: usize,
+    // objects being written, innermost last
+    objects: Vec<Object>,
+    // elements of the arrays being written, innermost last, when they are limited
+    arrays: Vec<usize>,
+    // bytes of the string being written, before escaping
+    string_len: usize,
+    // the violation reported once the outermost array or object is written
+    pending: Option<Violation>,
+    stats: Stats,
+}
 
-#[derive(Debug, Error)]
-pub enum CanonicalJSONError {
-    #[error("UTF-8 related error: {0}")]
-    Utf8Error(#[from] Utf8Error),
-    #[error("JSON related error: {0}")]
-    JSONError(#[from] serde_json::error::Error),
+/// Former name of [CanonicalFormatter].
+#[deprecated(note = "renamed to CanonicalFormatter")]
+pub type JsonFormatter = CanonicalFormatter;
+
+impl CanonicalFormatter {
+    /// Formatter with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counters collected so far by this formatter.
+    ///
+    /// `serde_json::Serializer` takes ownership of its formatter, so serialize with
+    /// `&mut formatter` to read them afterwards.
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
 }
 
-impl Formatter for JsonFormatter {
-    fn write_f64<W: ?Sized>(&mut self, writer: &mut W, value: f64) -> Result<(), std::io::Error>
-    where
-        W: Write,
-    {
-        format_number(writer, value)?;
-        Ok(())
+/// Counters collected while serializing.
+///
+/// A high escape count or expansion ratio points at inputs crafted to make the
+/// canonical form much larger than the original strings.
+///
+/// # Example
+///
+/// ```
+/// use canonical_json::Canonicalizer;
+/// use serde_json::json;
+///
+/// let (_, stats) = Canonicalizer::new().to_string_with_stats(&json!({"a": "\u{1}\u{2}"})).unwrap();
+/// assert_eq!(stats.escapes, 2);
+/// assert_eq!(stats.string_bytes, 3);
+/// assert_eq!(stats.escaped_string_bytes, 13);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Stats {
+    /// Escape sequences written in strings, keys included.
+    pub escapes: usize,
+    /// Bytes of string contents before escaping.
+    pub string_bytes: usize,
+    /// Bytes of string contents once escaped.
+    pub escaped_string_bytes: usize,
+}
+
+impl Stats {
+    /// Escaped string bytes per input string byte (`1.0` when there are no strings).
+    pub fn expansion_ratio(&self) -> f64 {
+        if self.string_bytes == 0 {
+            return 1.0;
+        }
+        self.escaped_string_bytes as f64 / self.string_bytes as f64
     }
+}
 
-    fn write_char_escape<W: ?Sized>(
-        &mut self,
-        writer: &mut W,
-        char_escape: CharEscape,
-    ) -> Result<(), std::io::Error>
-    where
-        W: Write,
-    {
-        match char_escape {
-            CharEscape::Quote => {
-                writer.write_all(b"\\\"")?;
-            }
-            CharEscape::ReverseSolidus => {
-                writer.write_all(b"\\\\")?;
-            }
-            CharEscape::LineFeed => {
-                writer.write_all(b"\\n")?;
-            }
-            CharEscape::Tab => {
-                writer.write_all(b"\\t")?;
-            }
-            CharEscape::CarriageReturn => {
-                writer.write_all(b"\\r")?;
-            }
-            CharEscape::Solidus => {
-                writer.write_all(b"\\/")?;
-            }
-            CharEscape::Backspace => {
-                writer.write_all(b"\\b")?;
-            }
-            CharEscape::FormFeed => {
-                writer.write_all(b"\\f")?;
-            }
-            CharEscape::AsciiControl(number) => {
-                static HEX_DIGITS: [u8; 16] = *b"0123456789abcdef";
-                let bytes = &[
-                    b'\\',
-                    b'u',
-                    b'0',
-                    b'0',
-                    HEX_DIGITS[(number >> 4) as usize],
-                    HEX_DIGITS[(number & 0xF) as usize],
-                ];
-                return writer.write_all(bytes);
+/// Failures that do not stop serialization, in order of precedence.
+#[derive(Debug, Clone)]
+enum Violation {
+    Members(usize),
+    ArrayLen(usize),
+    StringLen(usize),
+    DuplicateKey(String),
+    PrecisionLoss(String),
+}
+
+impl Violation {
+    fn rank(&self) -> u8 {
+        match self {
+            Violation::Members(_) => 0,
+            Violation::ArrayLen(_) => 1,
+            Violation::StringLen(_) => 2,
+            Violation::DuplicateKey(_) => 3,
+            Violation::PrecisionLoss(_) => 4,
+        }
+    }
+}
+
+impl From<Violation> for CanonicalJSONError {
+    fn from(violation: Violation) -> Self {
+        match violation {
+            Violation::Members(limit) => CanonicalJSONError::MemberLimitExceeded(limit),
+            Violation::ArrayLen(limit) => CanonicalJSONError::ArrayLimitExceeded(limit),
+            Violation::StringLen(limit) => {
+                CanonicalJSONError::SizeLimitExceeded { limit, line: None }
             }
+            Violation::DuplicateKey(key) => CanonicalJSONError::DuplicateKey(key),
+            Violation::PrecisionLoss(number) => CanonicalJSONError::PrecisionLoss(number),
         }
+    }
+}
 
-        Ok(())
+#[derive(Debug, Clone, Default)]
+struct Object {
+    members: Vec<Member>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Member {
+    // unescaped key, only when it differs from the serialized one
+    unescaped: Option<String>,
+    // length of the serialized key, quotes included
+    key_len: usize,
+    // serialized key, colon and value
+    bytes: Vec<u8>,
+}
+
+impl Member {
+    /// Unescaped key, used for ordering.
+    fn key(&self) -> &[u8] {
+        match &self.unescaped {
+            Some(key) => key.as_bytes(),
+            None => &self.bytes[1..self.key_len - 1],
+        }
     }
+}
 
-    fn write_string_fragment<W: ?Sized>(
-        &mut self,
-        writer: &mut W,
-        fragment: &str,
-    ) -> Result<(), std::io::Error>
-    where
-        W: Write,
-    {
-        let formatted_string = fragment
-            .to_string()
-            .escape_default()
-            .to_string()
-            .replace(r#"\'"#, "'");
+/// Where the formatter output goes: the member being written in the innermost
+/// object, or the actual writer outside of objects.
+enum Output<'a, W: ?Sized> {
+    Buffer(&'a mut Vec<u8>),
+    Writer(&'a mut W),
+}
+
+impl<W: ?Sized + Write> Write for Output<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Buffer(buffer) => buffer.write(buf),
+            Output::Writer(writer) => writer.write(buf),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            Output::Buffer(buffer) => buffer.write_all(buf),
+            Output::Writer(writer) => writer.write_all(buf),
+        }
+    }
 
-        normalize_unicode(writer, formatted_string).and(Ok(()))
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Buffer(_) => Ok(()),
+            Output::Writer(writer) => writer.flush(),
+        }
     }
 }
 
-fn format_number<W: ?Sized>(writer: &mut W, number: f64) -> Result<(), std::io::Error>
-where
-    W: Write,
-{
-    let formatted = format!("{:e}", number);
-    let normalized = normalize_number(formatted);
-    writer.write_all(&normalized.into_bytes())?;
-    Ok(())
+/// Counts the bytes and escape sequences written through it.
+struct Tally<W> {
+    inner: W,
+    bytes: usize,
+    escapes: usize,
 }
 
-// force capital-E exponent, remove + signs and leading zeroes
-fn normalize_number(input: String) -> String {
-    // https://github.com/gibson042/canonicaljson-go/blob/b9eb21a76/encode.go#L506-L514
-    let re = Regex::new("(?:E(?:[+]0*|(-|)0+)|e(?:[+]|(-|))0*)([0-9])").unwrap();
-    re.replace_all(&input, "E$1$2$3").to_string()
+impl<W: Write> Tally<W> {
+    fn new(inner: W) -> Self {
+        Tally {
+            inner,
+            bytes: 0,
+            escapes: 0,
+        }
+    }
 }
 
-/// look for \u{X} \u{XX}, \u{XXX}, \u{XXXX} to remove the curly braces
-fn normalize_unicode<W: ?Sized>(
-    writer: &mut W,
-    serialized_string: String,
-) -> Result<(), std::io::Error>
+impl<W: Write> Write for Tally<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.bytes += written;
+        // string contents only ever contain backslashes as the start of an escape
+        self.escapes += buf[..written].iter().filter(|&&byte| byte == b'\\').count();
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn output<'a, W>(objects: &'a mut [Object], writer: &'a mut W) -> Output<'a, W>
 where
-    W: Write,
+    W: ?Sized + Write,
 {
-    let mut string_iter = serialized_string.chars().peekable();
-
-    while let Some(curr_char) = string_iter.next() {
-        if curr_char == '\\' && string_iter.peek() == Some(&'u') {
-            writer.write_all("\\u".as_bytes())?;
-            string_iter.next();
-
-            if string_iter.peek() == Some(&'{') {
-                // consume at most 4 characters till '}' is found
-                let mut characters = String::new();
-                string_iter.next(); // skip the '{' for now
-                let mut index = 0;
-
-                while index < 6 && string_iter.peek() != Some(&'}') && string_iter.peek() != None {
-                    match string_iter.peek() {
-                        Some(character) => characters.push(*character),
-                        None => break,
-                    };
-
-                    string_iter.next();
-                    index += 1;
-                }
+    match objects
+        .last_mut()
+        .and_then(|object| object.members.last_mut())
+    {
+        Some(member) => Output::Buffer(&mut member.bytes),
+        None => Output::Writer(writer),
+    }
+}
 
-                if string_iter.peek() == None {
-                    // could not find '}' bracket so must include '{' and following characters
-                    writer.write_all("{".as_bytes())?;
-                    writer.write_all(&characters.into_bytes())?;
-                } else if string_iter.peek() == Some(&'}') {
-                    // found '}' - remove '{' and '}' but must pad zeros
-                    if characters.is_empty() {
-                        writer.write_all("{}".as_bytes())?;
-                    } else {
-                        if characters.len() > 4 {
-                            // Surrogates pairs.
-                            match hex::decode(format!("{:0>6}", characters)) {
-                                Ok(v) => {
-                                    let codepoint = (v[2] as u32)
-                                        + ((v[1] as u32) << 8)
-                                        + ((v[0] as u32) << 16);
-                                    let high = ((codepoint - 0x10000) / 0x400) + 0xD800;
-                                    let low = ((codepoint - 0x10000) % 0x400) + 0xDC00;
-                                    writer.write_all(format!("{:x}", high).as_bytes())?;
-                                    writer.write_all(format!("\\u{:x}", low).as_bytes())?;
-                                }
-                                Err(_) => {
-                                    writer.write_all(&characters.into_bytes())?;
-                                }
-                            };
-                        } else {
-                            writer.write_all(&"0".repeat(4 - characters.len()).into_bytes())?;
-                            writer.write_all(&characters.into_bytes())?;
-                        }
-                        string_iter.next(); // skip '}'
-                    }
-                }
-            }
+/// Policy for numbers that have no JSON representation (`NaN` and infinities).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonFinite {
+    /// Serialize them as `null`, like `serde_json` does.
+    #[default]
+    Null,
+    /// Fail with [CanonicalJSONError::NonFiniteNumber].
+    Error,
+}
 
-            continue;
-        }
+/// Unicode normalization applied to object keys when ordering them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NfKind {
+    /// Compare the raw keys.
+    #[default]
+    None,
+    /// Compare the canonical composition (NFC) of the keys.
+    Nfc,
+    /// Compare the compatibility composition (NFKC) of the keys.
+    Nfkc,
+}
 
-        writer.write_all(curr_char.to_string().as_bytes())?;
+impl NfKind {
+    fn apply(self, key: &[u8]) -> Cow<'_, [u8]> {
+        match self {
+            NfKind::None => Cow::Borrowed(key),
+            NfKind::Nfc => Cow::Owned(
+                String::from_utf8_lossy(key)
+                    .nfc()
+                    .collect::<String>()
+                    .into_bytes(),
+            ),
+            NfKind::Nfkc => Cow::Owned(
+                String::from_utf8_lossy(key)
+                    .nfkc()
+                    .collect::<String>()
+                    .into_bytes(),
+            ),
+        }
     }
-
-    Ok(())
 }
 
-/// Serialize a JSON value to String
-///
-/// # Examples
-/// ```rust
-/// # use canonical_json::ser::to_string;
-/// # use serde_json::json;
-/// # fn main() {
-///     to_string(&json!(null)); // returns "null"
+/// What to do with object members sharing the same key.
 ///
-///     to_string(&json!("test")); // returns "test"
+/// A `serde_json::Value` cannot hold duplicate keys: parsing one keeps the last
+/// value of each key. Documents given to [canonicalize_slice] and [canonicalize]
+/// keep all their members, though, and so can other `Serialize`
+/// implementations, so the policy applies to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateKeyPolicy {
+    /// Fail with [CanonicalJSONError::DuplicateKey].
+    #[default]
+    Error,
+    /// Keep the member serialized first.
+    FirstWins,
+    /// Keep the member serialized last, like `serde_json::Value` does when parsing.
+    LastWins,
+}
+
+/// How numbers are rounded to [Canonicalizer::max_significant_digits].
 ///
-///     to_string(&json!(10.0_f64.powf(21.0))); // returns "1e+21"
+/// Rounding applies to the shortest decimal representation of the number, i.e.
+/// to the digits that would be written without a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoundingMode {
+    /// Ties go to the even digit: `0.125` becomes `1.2E-1` with 2 digits.
+    #[default]
+    HalfEven,
+    /// Ties go away from zero: `0.125` becomes `1.3E-1` with 2 digits.
+    HalfUp,
+}
+
+/// How floating point numbers are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberFormat {
+    /// The spec's form: an exponent, capital `E`, no `+` sign nor leading
+    /// zeros (`1.0E21`, `1.5E0`).
+    ///
+    /// Values from 2^53 up to 2^64 (excluded), which are all integers, are
+    /// written as exact integers instead (`9007199254740992`).
+    #[default]
+    Canonical,
+    /// Legacy form, for compatibility with consumers of older versions and of
+    /// JavaScript's `JSON.stringify`: plain decimals between `1e-7` and `1e21`,
+    /// an exponent with its sign beyond (`1e+21`, `1.5`, `1e-7`).
+    ///
+    /// This is not canonical JSON per the spec, and is only meant to help
+    /// migrations.
+    Legacy,
+}
+
+/// Builder for canonical serialization with non-default options.
 ///
-///     to_string(&json!({
-///         "a": "a",
-///         "id": "1",
-///         "b": "b"
-///     })); // returns "{"a":"a","b":"b","id":"1"}"; (orders object keys)
+/// # Example
 ///
-///     to_string(&json!(vec!["one", "two", "three"])); // returns "["one","two","three"]"
-/// # }
+/// ```
+/// use canonical_json::ser::{Canonicalizer, NonFinite};
+/// use serde_json::json;
 ///
+/// let canonicalizer = Canonicalizer::new().non_finite(NonFinite::Error);
+/// assert_eq!(canonicalizer.to_string(&json!([1.5])).unwrap(), "[1.5E0]");
 /// ```
-pub fn to_string(input: &serde_json::Value) -> Result<String, CanonicalJSONError> {
-    let string = vec![];
-    let mut serializer = serde_json::Serializer::with_formatter(string, JsonFormatter);
-    input.serialize(&mut serializer)?;
-    let serialized_string = String::from_utf8(serializer.into_inner())?;
-    Ok(serialized_string)
+#[derive(Debug, Clone)]
+pub struct Canonicalizer {
+    non_finite: NonFinite,
+    max_depth: Option<usize>,
+    max_members: Option<usize>,
+    max_array_len: Option<usize>,
+    max_string_len: Option<usize>,
+    ascii_only: bool,
+    escape_bom: bool,
+    surrogate_pairs: bool,
+    sort_key_normalization: NfKind,
+    max_significant_digits: Option<usize>,
+    rounding_mode: RoundingMode,
+    number_format: NumberFormat,
+    plain_decimals: bool,
+    plain_exponents: RangeInclusive<i32>,
+    precision_loss_error: bool,
+    exact_numbers: bool,
+    emit_bom: bool,
+    duplicate_key_policy: DuplicateKeyPolicy,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::to_string;
-    use serde_json::json;
+impl Default for Canonicalizer {
+    fn default() -> Self {
+        Canonicalizer {
+            non_finite: NonFinite::default(),
+            max_depth: None,
+            max_members: None,
+            max_array_len: None,
+            max_string_len: None,
+            ascii_only: false,
+            escape_bom: false,
+            surrogate_pairs: true,
+            sort_key_normalization: NfKind::default(),
+            max_significant_digits: None,
+            rounding_mode: RoundingMode::default(),
+            number_format: NumberFormat::default(),
+            plain_decimals: false,
+            plain_exponents: -7..=20,
+            precision_loss_error: false,
+            exact_numbers: false,
+            emit_bom: false,
+            duplicate_key_policy: DuplicateKeyPolicy::default(),
+        }
+    }
+}
 
-    macro_rules! test_canonical_json {
-        ($v:tt, $e:expr) => {
-            match to_string(&json!($v)) {
-                Ok(serialized_string) => {
-                    println!("serialized is {}", serialized_string);
-                    assert_eq!(serialized_string, $e)
-                },
-                Err(error) => { panic!("error serializing input : {:?}", error) }
-            };
-        };
+impl Canonicalizer {
+    /// Builder with the default options, equivalent to [to_string].
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    #[test]
-    fn test_to_string() {
-        test_canonical_json!(null, "null");
-        test_canonical_json!((std::f64::NAN), "null");
-        test_canonical_json!((std::f64::INFINITY), "null");
-        test_canonical_json!((std::f64::NEG_INFINITY), "null");
-        test_canonical_json!(true, "true");
-        test_canonical_json!(false, "false");
-        test_canonical_json!(0, "0");
-        test_canonical_json!(123, "123");
-        test_canonical_json!((-123), "-123");
-        test_canonical_json!(23.1, "2.31E1");
-        test_canonical_json!(23, "23");
-        test_canonical_json!(1_f64, "1E0");
-        test_canonical_json!(0_f64, "0E0");
-        test_canonical_json!(23.0, "2.3E1");
-        test_canonical_json!((-23.0), "-2.3E1");
-        test_canonical_json!(2300, "2300");
-        test_canonical_json!(0.00099, "9.9E-4");
-        test_canonical_json!(0.000011, "1.1E-5");
-        test_canonical_json!(0.0000011, "1.1E-6");
-        test_canonical_json!(0.000001, "1E-6");
-        test_canonical_json!(5.6, "5.6E0");
-        test_canonical_json!(0.00000099, "9.9E-7");
-        test_canonical_json!(0.0000001, "1E-7");
-        test_canonical_json!(0.000000930258908, "9.30258908E-7");
-        test_canonical_json!(0.00000000000068272, "6.8272E-13");
-        test_canonical_json!((10.000_f64.powf(21.0)), "1E21");
-        test_canonical_json!((10.0_f64.powi(20)), "1E20");
-        test_canonical_json!((10.0_f64.powi(15) + 0.1), "1.0000000000000001E15");
-        test_canonical_json!((10.0_f64.powi(16) * 1.1), "1.1E16");
+    /// How `NaN` and infinite numbers are handled (default: [NonFinite::Null]).
+    ///
+    /// The policy applies to `f32` and `f64` values as well as to numbers stored
+    /// as strings with `serde_json`'s `arbitrary_precision` feature (e.g.
+    /// `1e400`). With [NonFinite::Error], values holding floats are looked
+    /// through once before being serialized, since `serde_json` writes
+    /// non-finite floats as `null` without going through the formatter.
+    pub fn non_finite(mut self, policy: NonFinite) -> Self {
+        self.non_finite = policy;
+        self
+    }
 
-        // serialize string
-        test_canonical_json!("", r#""""#);
-        //escape quotes
-        test_canonical_json!(
-            " Preserve single quotes'in string",
-            r#"" Preserve single quotes'in string""#
-        );
-        test_canonical_json!(" Escapes quotes \" ", r#"" Escapes quotes \" ""#);
-        test_canonical_json!("test", r#""test""#);
-        // escapes backslashes
-        test_canonical_json!("This\\and this", r#""This\\and this""#);
-        // convert unicode characters to unicode escape sequences
-        test_canonical_json!("I ❤ testing", r#""I \u2764 testing""#);
+    /// Fail with [CanonicalJSONError::DepthLimitExceeded] when arrays and objects
+    /// are nested more than `limit` levels deep.
+    ///
+    /// The check happens as the formatter is driven, so it also stops custom
+    /// `Serialize` implementations that keep recursing into arrays or objects,
+    /// before they overflow the stack.
+    pub fn max_depth(mut self, limit: usize) -> Self {
+        self.max_depth = Some(limit);
+        self
+    }
 
-        // serialize does not alter certain strings (newline, tab, carriagereturn, forwardslashes)
-        test_canonical_json!("This is a sentence.\n", r#""This is a sentence.\n""#);
-        test_canonical_json!("This is a \t tab.", r#""This is a \t tab.""#);
-        test_canonical_json!(
-            "This is a \r carriage return char.",
-            r#""This is a \r carriage return char.""#
-        );
-        test_canonical_json!("image/jpeg", r#""image/jpeg""#);
-        test_canonical_json!("image//jpeg", r#""image//jpeg""#);
-        // serialize preserves scientific notation number within string
-        test_canonical_json!("frequency at 10.0e+04", r#""frequency at 10.0e+04""#);
-        // serialize preserves invalid unicode escape sequence
-        test_canonical_json!("I \\u{} testing", r#""I \\u{} testing""#);
-        // serialize preserves opening curly brackets when invalid unicode escape sequence
-        test_canonical_json!("I \\u{1234 testing", r#""I \\u{1234 testing""#);
-        test_canonical_json!("I \\u{{12345}} testing", r#""I \\u{{12345}} testing""#);
+    /// Fail with [CanonicalJSONError::MemberLimitExceeded] when an object has
+    /// more than `limit` members.
+    pub fn max_members(mut self, limit: usize) -> Self {
+        self.max_members = Some(limit);
+        self
+    }
 
-        // surrogates pairs
-        test_canonical_json!("𝄞", r#""\ud834\udd1e""#);
-        test_canonical_json!("𝗠𝗼𝘇", r#""\ud835\udde0\ud835\uddfc\ud835\ude07""#);
-        // lowest and highest
-        test_canonical_json!("\u{10000} \u{10FFFF}", r#""\ud800\udc00 \udbff\udfff""#);
+    /// Fail with [CanonicalJSONError::ArrayLimitExceeded] when an array has
+    /// more than `limit` elements.
+    ///
+    /// Like the other limits, this bounds what is serialized: a document given
+    /// to [Canonicalizer::canonicalize_slice] is parsed in full first.
+    pub fn max_array_len(mut self, limit: usize) -> Self {
+        self.max_array_len = Some(limit);
+        self
+    }
 
-        // serialize object
-        test_canonical_json!(
-            {
-                "a": {},
-                "b": "b"
-            },
-            r#"{"a":{},"b":"b"}"#
-        );
+    /// Fail with [CanonicalJSONError::SizeLimitExceeded] when a string, object
+    /// keys included, is longer than `limit` bytes.
+    ///
+    /// Strings are measured in UTF-8 bytes before escaping, so the limit does
+    /// not depend on [Canonicalizer::ascii_only].
+    pub fn max_string_len(mut self, limit: usize) -> Self {
+        self.max_string_len = Some(limit);
+        self
+    }
 
-        // serialize object with keys ordered
-        test_canonical_json!(
-            {
-                "a": "a",
-                "id": "1",
-                "b": "b"
-            },
-            r#"{"a":"a","b":"b","id":"1"}"#
-        );
+    /// Escape every non-ASCII character as `\uXXXX` (default: `false`).
+    ///
+    /// When enabled, every byte of the output is guaranteed to be at most `0x7F`.
+    /// When disabled, as the spec requires, non-ASCII characters are written as
+    /// literal UTF-8, and only quotes, backslashes and control characters are
+    /// escaped.
+    pub fn ascii_only(mut self, enabled: bool) -> Self {
+        self.ascii_only = enabled;
+        self
+    }
 
-        // serialize deeply nested objects
-        test_canonical_json!(
-            {
-                "a": json!({
-                    "b": "b",
-                    "a": "a",
-                    "c": json!({
-                        "b": "b",
-                        "a": "a",
-                        "c": ["b", "a", "c"],
-                        "d": json!({ "b": "b", "a": "a" }),
-                        "id": "1",
-                        "e": 1,
-                        "f": [2, 3, 1],
-                        "g": json!({
-                            "2": 2,
-                            "3": 3,
-                            "1": json!({
-                                "b": "b",
-                                "a": "a",
-                                "c": "c",
-                            })
-                        })
-                    })
-                }),
-                "id": "1"
-            },
-            concat!(
-                r#"{"a":{"a":"a","b":"b","c":{"a":"a","b":"b","c":["b","a","c"],"#,
-                r#""d":{"a":"a","b":"b"},"e":1,"f":[2,3,1],"#,
-                r#""g":{"1":{"a":"a","b":"b","c":"c"},"2":2,"3":3},"id":"1"}},"id":"1"}"#
-            )
-        );
+    /// Escape the byte order mark (U+FEFF) as `\ufeff` even when
+    /// [Canonicalizer::ascii_only] is disabled (default: `false`).
+    ///
+    /// This keeps an invisible BOM embedded in a string visible in the output.
+    pub fn escape_bom(mut self, enabled: bool) -> Self {
+        self.escape_bom = enabled;
+        self
+    }
 
-        test_canonical_json!(
-            {
-                "b": vec!["two", "three"],
-                "a": vec!["zero", "one"]
-            },
-            r#"{"a":["zero","one"],"b":["two","three"]}"#
-        );
+    /// Start the output of [Canonicalizer::to_writer] with a UTF-8 byte order
+    /// mark, `EF BB BF` (default: `false`).
+    ///
+    /// The BOM is not part of the canonical form: it is only meant for tools
+    /// that expect it at the start of a file. Neither [Canonicalizer::to_vec]
+    /// nor [Canonicalizer::to_string] write it, and hashes never cover it.
+    pub fn emit_bom(mut self, enabled: bool) -> Self {
+        self.emit_bom = enabled;
+        self
+    }
+
+    /// Escape characters outside of the Basic Multilingual Plane as a UTF-16
+    /// surrogate pair (default: `true`).
+    ///
+    /// When disabled, they are escaped as a single `\u{XXXXX}` instead, e.g.
+    /// `\u{1f980}` for 🦀. That form is not JSON: it is only meant for consumers
+    /// that expect it, such as JavaScript source. Only applies to characters
+    /// escaped because of [Canonicalizer::ascii_only].
+    pub fn surrogate_pairs(mut self, enabled: bool) -> Self {
+        self.surrogate_pairs = enabled;
+        self
+    }
+
+    /// Order object members by the normalized form of their keys (default:
+    /// [NfKind::None]).
+    ///
+    /// The normalization only applies to the comparison: keys are emitted as
+    /// they are. Keys that normalize to the same form are ordered as raw
+    /// keys, so that the output does not depend on the order members are
+    /// serialized in. Such keys are distinct: [Canonicalizer::duplicate_key_policy]
+    /// only applies to identical raw keys.
+    pub fn sort_key_normalization(mut self, kind: NfKind) -> Self {
+        self.sort_key_normalization = kind;
+        self
+    }
+
+    /// Round floating point numbers to at most `limit` significant digits.
+    ///
+    /// Integers (values not stored as `f64`) are written in full whatever the
+    /// limit. See [RoundingMode] for how ties are broken.
+    ///
+    /// # Panics
+    ///
+    /// If `limit` is 0.
+    pub fn max_significant_digits(mut self, limit: usize) -> Self {
+        assert!(limit > 0, "at least one significant digit is needed");
+        self.max_significant_digits = Some(limit);
+        self
+    }
+
+    /// How numbers are rounded to [Canonicalizer::max_significant_digits]
+    /// (default: [RoundingMode::HalfEven]).
+    pub fn rounding_mode(mut self, mode: RoundingMode) -> Self {
+        self.rounding_mode = mode;
+        self
+    }
+
+    /// How floating point numbers are written (default: [NumberFormat::Canonical]).
+    pub fn number_format(mut self, format: NumberFormat) -> Self {
+        self.number_format = format;
+        self
+    }
+
+    /// Write floating point numbers in plain decimal notation, e.g.
+    /// `0.0000001` rather than `1.0E-7`, when their exponent is within
+    /// [Canonicalizer::plain_decimal_exponents] (default: `false`).
+    ///
+    /// Numbers outside of the range keep the notation of
+    /// [Canonicalizer::number_format]. This is not part of the canonical JSON
+    /// spec, and integral floats lose their distinction from integers (`1.0E0`
+    /// becomes `1`).
+    pub fn plain_decimals(mut self, enabled: bool) -> Self {
+        self.plain_decimals = enabled;
+        self
+    }
+
+    /// Decimal exponents of the numbers written in plain notation by
+    /// [Canonicalizer::plain_decimals] (default: `-7..=20`).
+    pub fn plain_decimal_exponents(mut self, exponents: RangeInclusive<i32>) -> Self {
+        self.plain_exponents = exponents;
+        self
+    }
+
+    /// Fail with [CanonicalJSONError::PrecisionLoss] when a number would be
+    /// written back with a different value once stored in an `f64` (default:
+    /// `false`), e.g. `1.0000000000000001`, which becomes `1.0E0`.
+    ///
+    /// This needs the text of numbers, which is only kept with `serde_json`'s
+    /// `arbitrary_precision` feature: without it, numbers are already rounded
+    /// when the input is parsed. Rounding to
+    /// [Canonicalizer::max_significant_digits] is not a loss.
+    pub fn fail_on_precision_loss(mut self, enabled: bool) -> Self {
+        self.precision_loss_error = enabled;
+        self
+    }
+
+    /// Write numbers kept as text with all of their digits instead of going
+    /// through an `f64` (default: `false`): `1.0000000000000001` stays
+    /// `1.0000000000000001E0` and `1e-400` stays `1.0E-400`.
+    ///
+    /// Like [Canonicalizer::fail_on_precision_loss], this only applies with
+    /// `serde_json`'s `arbitrary_precision` feature. Numbers that an `f64` holds
+    /// exactly are written the same either way. Numbers too large for an `f64`
+    /// are still handled by [Canonicalizer::non_finite].
+    pub fn exact_numbers(mut self, enabled: bool) -> Self {
+        self.exact_numbers = enabled;
+        self
+    }
+
+    /// What to do with object members sharing the same key (default:
+    /// [DuplicateKeyPolicy::Error]).
+    pub fn duplicate_key_policy(mut self, policy: DuplicateKeyPolicy) -> Self {
+        self.duplicate_key_policy = policy;
+        self
+    }
+
+    /// A [CanonicalFormatter] applying these options.
+    pub fn formatter(&self) -> CanonicalFormatter {
+        CanonicalFormatter {
+            options: self.clone(),
+            ..CanonicalFormatter::default()
+        }
+    }
+
+    /// Serialize a JSON value to String, see [to_string].
+    pub fn to_string<V>(&self, input: &V) -> Result<String, CanonicalJSONError>
+    where
+        V: ?Sized + Serialize,
+    {
+        Ok(String::from_utf8(self.to_vec(input)?)?)
+    }
+
+    /// Serialize a JSON value to bytes, see [to_vec].
+    pub fn to_vec<V>(&self, input: &V) -> Result<Vec<u8>, CanonicalJSONError>
+    where
+        V: ?Sized + Serialize,
+    {
+        let mut bytes = vec![];
+        self.write_canonical(&mut bytes, input)?;
+        Ok(bytes)
+    }
+
+    /// Serialize a JSON value to String, also returning the [Stats] collected
+    /// along the way.
+    pub fn to_string_with_stats<V>(&self, input: &V) -> Result<(String, Stats), CanonicalJSONError>
+    where
+        V: ?Sized + Serialize,
+    {
+        self.check_finite(input)?;
+        let mut formatter = self.formatter();
+        let mut serializer = serde_json::Serializer::with_formatter(vec![], &mut formatter);
+        input.serialize(&mut serializer)?;
+        let serialized_string = String::from_utf8(serializer.into_inner())?;
+        Ok((serialized_string, formatter.stats()))
+    }
+
+    /// Parse and canonicalize a JSON document, see [canonicalize_slice].
+    pub fn canonicalize_slice(&self, input: &[u8]) -> Result<String, CanonicalJSONError> {
+        let mut bytes = vec![];
+        self.write_canonical_slice(&mut bytes, input)?;
+        let serialized_string = String::from_utf8(bytes)?;
+        Ok(serialized_string)
+    }
+
+    /// [Canonicalizer::canonicalize_slice] into a writer.
+    pub(crate) fn write_canonical_slice<W>(
+        &self,
+        writer: W,
+        input: &[u8],
+    ) -> Result<(), CanonicalJSONError>
+    where
+        W: Write,
+    {
+        let mut deserializer = serde_json::Deserializer::from_slice(input);
+        let value = Borrowed::deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        self.write_canonical(writer, &value)
+    }
+
+    /// Serialize a JSON value into a writer, see [to_writer].
+    pub fn to_writer<W, V>(&self, mut writer: W, input: &V) -> Result<(), CanonicalJSONError>
+    where
+        W: Write,
+        V: ?Sized + Serialize,
+    {
+        if self.emit_bom {
+            writer
+                .write_all(b"\xEF\xBB\xBF")
+                .map_err(CanonicalJSONError::Io)?;
+        }
+        self.write_canonical(writer, input)
+    }
+
+    /// [Canonicalizer::to_writer] without the byte order mark.
+    pub(crate) fn write_canonical<W, V>(
+        &self,
+        writer: W,
+        input: &V,
+    ) -> Result<(), CanonicalJSONError>
+    where
+        W: Write,
+        V: ?Sized + Serialize,
+    {
+        self.check_finite(input)?;
+        let mut serializer = serde_json::Serializer::with_formatter(writer, self.formatter());
+        input.serialize(&mut serializer)?;
+        Ok(())
+    }
+
+    /// Fails with [CanonicalJSONError::NonFiniteNumber] when `input` holds a
+    /// non-finite float that [NonFinite::Error] rejects.
+    pub(crate) fn check_finite<V>(&self, input: &V) -> Result<(), CanonicalJSONError>
+    where
+        V: ?Sized + Serialize,
+    {
+        if self.non_finite == NonFinite::Null {
+            return Ok(());
+        }
+        match first_non_finite(input, self.max_depth)? {
+            Some(value) => Err(CanonicalJSONError::NonFiniteNumber(value.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Errors of canonicalization.
+///
+/// When a value breaks several rules at once, the error returned is the first
+/// of, in this order:
+///
+/// 1. [CanonicalJSONError::JSONError] for a malformed document, which is parsed
+///    in full before anything is serialized;
+/// 2. [CanonicalJSONError::NonFiniteNumber], as values are looked through for
+///    non-finite numbers before being serialized, down to the depth limit;
+/// 3. [CanonicalJSONError::DepthLimitExceeded], which stops serialization right
+///    away;
+/// 4. [CanonicalJSONError::MemberLimitExceeded];
+/// 5. [CanonicalJSONError::ArrayLimitExceeded];
+/// 6. [CanonicalJSONError::SizeLimitExceeded], for a string;
+/// 7. [CanonicalJSONError::DuplicateKey];
+/// 8. [CanonicalJSONError::PrecisionLoss].
+///
+/// Breaking one of the last five does not stop serialization: the error is
+/// returned once the outermost array or object is written, so that it does
+/// not depend on where the violations are. Among violations of the same rule,
+/// the first one serialized is reported. Errors of the writer are returned as
+/// soon as they happen.
+#[derive(Debug, Error)]
+pub enum CanonicalJSONError {
+    #[error("UTF-8 related error: {0}")]
+    Utf8Error(#[from] Utf8Error),
+    #[error("JSON related error: {0}")]
+    JSONError(#[source] serde_json::error::Error),
+    #[error("non-finite number: {0}")]
+    NonFiniteNumber(String),
+    #[error("nesting depth exceeds the limit of {0}")]
+    DepthLimitExceeded(usize),
+    #[error("object members exceed the limit of {0}")]
+    MemberLimitExceeded(usize),
+    #[error("array elements exceed the limit of {0}")]
+    ArrayLimitExceeded(usize),
+    #[error("canonical output exceeds the limit of {limit} bytes{}", .line.map(|line| format!(" at line {}", line)).unwrap_or_default())]
+    SizeLimitExceeded { limit: usize, line: Option<usize> },
+    #[error("canonical output does not fit in a buffer of {0} bytes")]
+    BufferOverflow(usize),
+    #[error("duplicate object key: {0}")]
+    DuplicateKey(String),
+    #[error("invalid path expression: {0}")]
+    InvalidPath(String),
+    #[error("number cannot be represented without losing precision: {0}")]
+    PrecisionLoss(String),
+    #[error("input is not in canonical form")]
+    NotCanonical { expected: String, found: String },
+    #[error("source item error: {0}")]
+    Item(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("I/O error: {0}")]
+    Io(#[source] io::Error),
+}
+
+impl From<serde_json::error::Error> for CanonicalJSONError {
+    fn from(error: serde_json::error::Error) -> Self {
+        if !error.is_io() {
+            return CanonicalJSONError::JSONError(error);
+        }
+        // errors raised by the formatter travel through serde_json as I/O errors
+        let error = io::Error::from(error);
+        let ours = error
+            .get_ref()
+            .is_some_and(|inner| inner.is::<CanonicalJSONError>());
+        if ours {
+            return *error.into_inner().unwrap().downcast().unwrap();
+        }
+        CanonicalJSONError::Io(error)
+    }
+}
+
+/// Errors of the writer come back as they were, any other failure as an
+/// [io::ErrorKind::InvalidData] error wrapping the [CanonicalJSONError].
+impl From<CanonicalJSONError> for io::Error {
+    fn from(error: CanonicalJSONError) -> Self {
+        match error {
+            CanonicalJSONError::Io(error) => error,
+            error => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
+
+impl CanonicalJSONError {
+    /// The kind of the underlying I/O error, if the writer failed.
+    ///
+    /// ```
+    /// # use canonical_json::CanonicalJSONError;
+    /// # use std::io;
+    /// let error = CanonicalJSONError::Io(io::Error::from(io::ErrorKind::BrokenPipe));
+    /// assert_eq!(error.io_kind(), Some(io::ErrorKind::BrokenPipe));
+    /// ```
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            CanonicalJSONError::Io(error) => Some(error.kind()),
+            _ => None,
+        }
+    }
+}
+
+/// Wraps an error so that it can be returned from a [Formatter] method and
+/// recovered by `From<serde_json::Error>`.
+fn formatter_error(error: CanonicalJSONError) -> io::Error {
+    io::Error::other(error)
+}
+
+impl CanonicalFormatter {
+    fn enter(&mut self) -> Result<(), std::io::Error> {
+        self.depth += 1;
+        match self.options.max_depth {
+            Some(limit) if self.depth > limit => Err(formatter_error(
+                CanonicalJSONError::DepthLimitExceeded(limit),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn add_string_len(&mut self, len: usize) -> Result<(), std::io::Error> {
+        self.string_len += len;
+        match self.options.max_string_len {
+            Some(limit) if self.string_len > limit => self.violation(Violation::StringLen(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    // keep the violation of highest precedence, the first one among equals,
+    // until the outermost array or object is written
+    fn violation(&mut self, violation: Violation) -> Result<(), std::io::Error> {
+        let replace = self
+            .pending
+            .as_ref()
+            .is_none_or(|pending| violation.rank() < pending.rank());
+        if replace {
+            self.pending = Some(violation);
+        }
+        if self.depth == 0 {
+            return self.leave();
+        }
+        Ok(())
+    }
+
+    // report the pending violation once the outermost value is written
+    fn leave(&mut self) -> Result<(), std::io::Error> {
+        match self.pending.take() {
+            Some(violation) if self.depth == 0 => Err(formatter_error(violation.into())),
+            pending => {
+                self.pending = pending;
+                Ok(())
+            }
+        }
+    }
+
+    fn write_non_finite<W>(&mut self, writer: &mut W, repr: &str) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        match self.options.non_finite {
+            NonFinite::Null => self.write_null(writer),
+            NonFinite::Error => Err(formatter_error(CanonicalJSONError::NonFiniteNumber(
+                repr.to_string(),
+            ))),
+        }
+    }
+}
+
+impl Formatter for CanonicalFormatter {
+    fn write_null<W>(&mut self, writer: &mut W) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        CompactFormatter.write_null(&mut output(&mut self.objects, writer))
+    }
+
+    fn write_bool<W>(&mut self, writer: &mut W, value: bool) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        CompactFormatter.write_bool(&mut output(&mut self.objects, writer), value)
+    }
+
+    fn write_i8<W>(&mut self, writer: &mut W, value: i8) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        CompactFormatter.write_i8(&mut output(&mut self.objects, writer), value)
+    }
+
+    fn write_i16<W>(&mut self, writer: &mut W, value: i16) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        CompactFormatter.write_i16(&mut output(&mut self.objects, writer), value)
+    }
+
+    fn write_i32<W>(&mut self, writer: &mut W, value: i32) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        CompactFormatter.write_i32(&mut output(&mut self.objects, writer), value)
+    }
+
+    fn write_i64<W>(&mut self, writer: &mut W, value: i64) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        CompactFormatter.write_i64(&mut output(&mut self.objects, writer), value)
+    }
+
+    fn write_i128<W>(&mut self, writer: &mut W, value: i128) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        CompactFormatter.write_i128(&mut output(&mut self.objects, writer), value)
+    }
+
+    fn write_u8<W>(&mut self, writer: &mut W, value: u8) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        CompactFormatter.write_u8(&mut output(&mut self.objects, writer), value)
+    }
+
+    fn write_u16<W>(&mut self, writer: &mut W, value: u16) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        CompactFormatter.write_u16(&mut output(&mut self.objects, writer), value)
+    }
+
+    fn write_u32<W>(&mut self, writer: &mut W, value: u32) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        CompactFormatter.write_u32(&mut output(&mut self.objects, writer), value)
+    }
+
+    fn write_u64<W>(&mut self, writer: &mut W, value: u64) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        CompactFormatter.write_u64(&mut output(&mut self.objects, writer), value)
+    }
+
+    fn write_u128<W>(&mut self, writer: &mut W, value: u128) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        CompactFormatter.write_u128(&mut output(&mut self.objects, writer), value)
+    }
+
+    fn write_f32<W>(&mut self, writer: &mut W, value: f32) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        // widened from its shortest representation, so that `0.1_f32` is
+        // written as `1.0E-1` rather than with the digits of its binary value
+        let widened = match format!("{:e}", value).parse() {
+            Ok(widened) if value.is_finite() => widened,
+            _ => f64::from(value),
+        };
+        self.write_f64(writer, widened)
+    }
+
+    fn begin_string<W>(&mut self, writer: &mut W) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        self.string_len = 0;
+        output(&mut self.objects, writer).write_all(b"\"")
+    }
+
+    fn end_string<W>(&mut self, writer: &mut W) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        output(&mut self.objects, writer).write_all(b"\"")
+    }
+
+    fn begin_array<W>(&mut self, writer: &mut W) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        self.enter()?;
+        if self.options.max_array_len.is_some() {
+            self.arrays.push(0);
+        }
+        output(&mut self.objects, writer).write_all(b"[")
+    }
+
+    fn end_array<W>(&mut self, writer: &mut W) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        self.depth -= 1;
+        if self.options.max_array_len.is_some() {
+            self.arrays.pop();
+        }
+        output(&mut self.objects, writer).write_all(b"]")?;
+        self.leave()
+    }
+
+    fn begin_array_value<W>(&mut self, writer: &mut W, first: bool) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        if let Some(limit) = self.options.max_array_len {
+            let len = self
+                .arrays
+                .last_mut()
+                .expect("array value outside of an array");
+            *len += 1;
+            if *len > limit {
+                self.violation(Violation::ArrayLen(limit))?;
+            }
+        }
+        if first {
+            Ok(())
+        } else {
+            output(&mut self.objects, writer).write_all(b",")
+        }
+    }
+
+    fn begin_object<W>(&mut self, _writer: &mut W) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        self.enter()?;
+        self.objects.push(Object::default());
+        Ok(())
+    }
+
+    fn begin_object_key<W>(&mut self, _writer: &mut W, _first: bool) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        let object = self
+            .objects
+            .last_mut()
+            .expect("object key outside of an object");
+        object.members.push(Member::default());
+        match self.options.max_members {
+            Some(limit) if object.members.len() > limit => {
+                self.violation(Violation::Members(limit))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn end_object_key<W>(&mut self, _writer: &mut W) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        let member = self
+            .objects
+            .last_mut()
+            .and_then(|object| object.members.last_mut())
+            .expect("object key outside of an object");
+        // keys of any type are written as JSON strings: the unescaped key is
+        // usually the serialized one without its quotes, and is only read back
+        // when it contains escapes
+        member.key_len = member.bytes.len();
+        let quoted = &member.bytes[1..member.key_len - 1];
+        if quoted.contains(&b'\\') {
+            let unescaped = if self.options.surrogate_pairs {
+                serde_json::from_slice(&member.bytes)
+            } else {
+                serde_json::from_str(&unescape_braces(&member.bytes))
+            };
+            member.unescaped =
+                Some(unescaped.map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?);
+        }
+        Ok(())
+    }
+
+    fn begin_object_value<W>(&mut self, writer: &mut W) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        output(&mut self.objects, writer).write_all(b":")
+    }
+
+    fn end_object<W>(&mut self, writer: &mut W) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        self.depth -= 1;
+        let object = self
+            .objects
+            .pop()
+            .expect("end of object outside of an object");
+        let members = &object.members;
+        // sort positions rather than the members themselves, and not at all
+        // when they are already in order (e.g. values coming from a `BTreeMap`)
+        let normalization = self.options.sort_key_normalization;
+        let keys: Vec<Cow<[u8]>> = members
+            .iter()
+            .map(|member| normalization.apply(member.key()))
+            .collect();
+        let compare = |a: usize, b: usize| {
+            cmp_utf16(&keys[a], &keys[b])
+                .then_with(|| cmp_utf16(members[a].key(), members[b].key()))
+        };
+        let mut order: Vec<usize> = (0..members.len()).collect();
+        let sorted = (1..members.len()).all(|i| compare(i - 1, i) != Ordering::Greater);
+        if !sorted {
+            order.sort_by(|&a, &b| compare(a, b));
+        }
+        // the sort is stable: duplicate keys are adjacent, in serialization order
+        let policy = self.options.duplicate_key_policy;
+        let mut duplicate = None;
+        order.dedup_by(|later, earlier| {
+            let same = members[*later].key() == members[*earlier].key();
+            if same {
+                match policy {
+                    DuplicateKeyPolicy::Error => duplicate = duplicate.or(Some(*earlier)),
+                    DuplicateKeyPolicy::FirstWins => {}
+                    DuplicateKeyPolicy::LastWins => *earlier = *later,
+                }
+            }
+            same
+        });
+        if let Some(index) = duplicate {
+            let key = String::from_utf8_lossy(members[index].key()).into_owned();
+            self.violation(Violation::DuplicateKey(key))?;
+        }
+
+        let mut output = output(&mut self.objects, writer);
+        output.write_all(b"{")?;
+        for (position, &index) in order.iter().enumerate() {
+            if position > 0 {
+                output.write_all(b",")?;
+            }
+            output.write_all(&members[index].bytes)?;
+        }
+        output.write_all(b"}")?;
+        self.leave()
+    }
+
+    fn write_raw_fragment<W>(
+        &mut self,
+        writer: &mut W,
+        fragment: &str,
+    ) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        output(&mut self.objects, writer).write_all(fragment.as_bytes())
+    }
+
+    fn write_f64<W>(&mut self, writer: &mut W, value: f64) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        if !value.is_finite() {
+            return self.write_non_finite(writer, &value.to_string());
+        }
+        // `-0.0 == 0.0`: negative zero is written as zero
+        let value = if value == 0.0 { 0.0 } else { value };
+        format_number(&mut output(&mut self.objects, writer), value, &self.options)
+    }
+
+    fn write_number_str<W>(&mut self, writer: &mut W, value: &str) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        // with `arbitrary_precision`, numbers are kept as they were written:
+        // integers are written exactly, other numbers through an `f64` unless
+        // `exact_numbers` is set
+        let digits = value.strip_prefix('-').unwrap_or(value);
+        if !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            // parsers reject leading zeros, but nothing stops a `Serialize`
+            // implementation from handing them over
+            let mut output = output(&mut self.objects, writer);
+            return match digits.trim_start_matches('0') {
+                // `-0` too
+                "" => output.write_all(b"0"),
+                significant => {
+                    if digits.len() < value.len() {
+                        output.write_all(b"-")?;
+                    }
+                    output.write_all(significant.as_bytes())
+                }
+            };
+        }
+        match value.parse::<f64>() {
+            Ok(number) if number.is_finite() => {
+                if self.options.exact_numbers {
+                    let mut output = output(&mut self.objects, writer);
+                    if let Some(result) = format_exact(&mut output, value, number, &self.options) {
+                        return result;
+                    }
+                }
+                if self.options.precision_loss_error
+                    && decimal_value(value) != decimal_value(&format!("{:e}", number))
+                {
+                    self.violation(Violation::PrecisionLoss(value.to_string()))?;
+                }
+                self.write_f64(writer, number)
+            }
+            Ok(_) => self.write_non_finite(writer, value),
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid number: {}", value),
+            )),
+        }
+    }
+
+    fn write_char_escape<W>(
+        &mut self,
+        writer: &mut W,
+        char_escape: CharEscape,
+    ) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        self.add_string_len(1)?;
+        let mut writer = Tally::new(output(&mut self.objects, writer));
+        write_char_escape(&mut writer, char_escape)?;
+        self.stats.escapes += 1;
+        self.stats.string_bytes += 1;
+        self.stats.escaped_string_bytes += writer.bytes;
+        Ok(())
+    }
+
+    fn write_string_fragment<W>(
+        &mut self,
+        writer: &mut W,
+        fragment: &str,
+    ) -> Result<(), std::io::Error>
+    where
+        W: ?Sized + Write,
+    {
+        self.add_string_len(fragment.len())?;
+        let mut writer = Tally::new(output(&mut self.objects, writer));
+        write_escaped(&mut writer, fragment, &self.options)?;
+        self.stats.escapes += writer.escapes;
+        self.stats.string_bytes += fragment.len();
+        self.stats.escaped_string_bytes += writer.bytes;
+        Ok(())
+    }
+}
+
+macro_rules! forward {
+    ($($method:ident($($arg:ident: $type:ty),*);)*) => {
+        $(
+            fn $method<W>(&mut self, writer: &mut W $(, $arg: $type)*) -> Result<(), std::io::Error>
+            where
+                W: ?Sized + Write,
+            {
+                (**self).$method(writer $(, $arg)*)
+            }
+        )*
+    };
+}
+
+/// Lets a `serde_json::Serializer` borrow the formatter, so that its state
+/// (e.g. [CanonicalFormatter::stats]) can be read once serialization is done.
+impl Formatter for &mut CanonicalFormatter {
+    forward! {
+        write_null();
+        write_bool(value: bool);
+        write_i8(value: i8);
+        write_i16(value: i16);
+        write_i32(value: i32);
+        write_i64(value: i64);
+        write_i128(value: i128);
+        write_u8(value: u8);
+        write_u16(value: u16);
+        write_u32(value: u32);
+        write_u64(value: u64);
+        write_u128(value: u128);
+        write_f32(value: f32);
+        write_f64(value: f64);
+        write_number_str(value: &str);
+        begin_string();
+        end_string();
+        write_string_fragment(fragment: &str);
+        write_char_escape(char_escape: CharEscape);
+        write_byte_array(value: &[u8]);
+        begin_array();
+        end_array();
+        begin_array_value(first: bool);
+        end_array_value();
+        begin_object();
+        end_object();
+        begin_object_key(first: bool);
+        end_object_key();
+        begin_object_value();
+        end_object_value();
+        write_raw_fragment(fragment: &str);
+    }
+}
+
+fn write_char_escape<W>(writer: &mut W, char_escape: CharEscape) -> Result<(), std::io::Error>
+where
+    W: ?Sized + Write,
+{
+    match char_escape {
+        CharEscape::Quote => {
+            writer.write_all(b"\\\"")?;
+        }
+        CharEscape::ReverseSolidus => {
+            writer.write_all(b"\\\\")?;
+        }
+        CharEscape::LineFeed => {
+            writer.write_all(b"\\n")?;
+        }
+        CharEscape::Tab => {
+            writer.write_all(b"\\t")?;
+        }
+        CharEscape::CarriageReturn => {
+            writer.write_all(b"\\r")?;
+        }
+        CharEscape::Solidus => {
+            writer.write_all(b"\\/")?;
+        }
+        CharEscape::Backspace => {
+            writer.write_all(b"\\b")?;
+        }
+        CharEscape::FormFeed => {
+            writer.write_all(b"\\f")?;
+        }
+        CharEscape::AsciiControl(number) => {
+            static HEX_DIGITS: [u8; 16] = *b"0123456789abcdef";
+            let bytes = &[
+                b'\\',
+                b'u',
+                b'0',
+                b'0',
+                HEX_DIGITS[(number >> 4) as usize],
+                HEX_DIGITS[(number & 0xF) as usize],
+            ];
+            return writer.write_all(bytes);
+        }
+    }
+
+    Ok(())
+}
+
+fn format_number<W>(
+    writer: &mut W,
+    number: f64,
+    options: &Canonicalizer,
+) -> Result<(), std::io::Error>
+where
+    W: ?Sized + Write,
+{
+    if options.number_format == NumberFormat::Canonical
+        && options.max_significant_digits.is_none()
+        && is_large_integer(number)
+    {
+        return write!(writer, "{:.0}", number);
+    }
+    format_scientific(writer, format!("{:e}", number), options)
+}
+
+// write `[-]d[.ddd]e<exp>`, Rust's scientific notation, in the output format
+fn format_scientific<W>(
+    writer: &mut W,
+    mut formatted: String,
+    options: &Canonicalizer,
+) -> Result<(), std::io::Error>
+where
+    W: ?Sized + Write,
+{
+    if let Some(limit) = options.max_significant_digits {
+        formatted = round_significant(&formatted, limit, options.rounding_mode);
+    }
+    let plain = if options.plain_decimals {
+        plain_number(&formatted, &options.plain_exponents)
+    } else {
+        None
+    };
+    let normalized = match (plain, options.number_format) {
+        (Some(plain), _) => plain,
+        (None, NumberFormat::Canonical) => normalize_number(formatted),
+        (None, NumberFormat::Legacy) => legacy_number(&formatted),
+    };
+    writer.write_all(&normalized.into_bytes())?;
+    Ok(())
+}
+
+// split `[-]d[.ddd]e<exp>` into its sign, digits and exponent
+fn split_scientific(formatted: &str) -> (&str, String, i32) {
+    let (mantissa, exponent) = formatted
+        .split_once('e')
+        .expect("exponent in scientific notation");
+    let exponent: i32 = exponent.parse().expect("integer exponent");
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let digits = mantissa.chars().filter(|&c| c != '.').collect();
+    (sign, digits, exponent)
+}
+
+/// Exact value of a JSON number, as its sign, significant digits and the
+/// power of ten they are multiplied by, or `None` for an exponent out of range.
+/// Zero has no digits, so that all of its forms are equal.
+fn decimal_value(number: &str) -> Option<(bool, String, i64)> {
+    let (negative, number) = match number.strip_prefix('-') {
+        Some(number) => (true, number),
+        None => (false, number),
+    };
+    let (mantissa, exponent) = number.split_once(['e', 'E']).unwrap_or((number, "0"));
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = format!("{}{}", integer, fraction);
+    let significant = digits.trim_start_matches('0').trim_end_matches('0');
+    if significant.is_empty() {
+        return Some((false, String::new(), 0));
+    }
+    let exponent = exponent.parse::<i64>().ok()?;
+    let trailing_zeros = digits.len() - digits.trim_end_matches('0').len();
+    let power = exponent
+        .checked_sub(i64::try_from(fraction.len()).ok()?)?
+        .checked_add(i64::try_from(trailing_zeros).ok()?)?;
+    Some((negative, significant.to_string(), power))
+}
+
+// the digits of a decimal number kept as text, in the same form as
+// `format_number`; `None` for zero, whose sign only an `f64` tells
+fn format_exact<W>(
+    writer: &mut W,
+    number: &str,
+    value: f64,
+    options: &Canonicalizer,
+) -> Option<Result<(), std::io::Error>>
+where
+    W: ?Sized + Write,
+{
+    let (negative, digits, power) = decimal_value(number)?;
+    if digits.is_empty() {
+        return None;
+    }
+    let count = i64::try_from(digits.len()).ok()?;
+    let exponent = i32::try_from(power.checked_add(count - 1)?).ok()?;
+    let sign = if negative { "-" } else { "" };
+    if options.number_format == NumberFormat::Canonical
+        && options.max_significant_digits.is_none()
+        && power >= 0
+        && is_large_integer(value)
+    {
+        let zeros = "0".repeat(usize::try_from(power).ok()?);
+        return Some(write!(writer, "{}{}{}", sign, digits, zeros));
+    }
+    let (first, rest) = digits.split_at(1);
+    let formatted = match rest {
+        "" => format!("{}{}e{}", sign, first, exponent),
+        rest => format!("{}{}.{}e{}", sign, first, rest, exponent),
+    };
+    Some(format_scientific(writer, formatted, options))
+}
+
+// turn `[-]d[.ddd]e<exp>` into plain decimal notation, if `exp` is in range
+fn plain_number(formatted: &str, exponents: &RangeInclusive<i32>) -> Option<String> {
+    let (sign, digits, exponent) = split_scientific(formatted);
+    if digits == "0" {
+        // negative zero too
+        return Some("0".to_string());
+    }
+    if !exponents.contains(&exponent) {
+        return None;
+    }
+    // position of the decimal point relative to the digits
+    let point = exponent + 1;
+    let count = digits.len() as i32;
+    Some(if count <= point {
+        format!("{}{}{}", sign, digits, "0".repeat((point - count) as usize))
+    } else if 0 < point {
+        let (integer, fraction) = digits.split_at(point as usize);
+        format!("{}{}.{}", sign, integer, fraction)
+    } else {
+        format!("{}0.{}{}", sign, "0".repeat(-point as usize), digits)
+    })
+}
+
+// turn `[-]d[.ddd]e<exp>` into ECMAScript's `Number.prototype.toString` form
+fn legacy_number(formatted: &str) -> String {
+    let (sign, digits, exponent) = split_scientific(formatted);
+    if digits == "0" {
+        // negative zero too
+        return "0".to_string();
+    }
+    // position of the decimal point relative to the digits
+    let point = exponent + 1;
+    let count = digits.len() as i32;
+    if count <= point && point <= 21 {
+        format!("{}{}{}", sign, digits, "0".repeat((point - count) as usize))
+    } else if 0 < point && point <= 21 {
+        let (integer, fraction) = digits.split_at(point as usize);
+        format!("{}{}.{}", sign, integer, fraction)
+    } else if -6 < point && point <= 0 {
+        format!("{}0.{}{}", sign, "0".repeat(-point as usize), digits)
+    } else {
+        let (first, fraction) = digits.split_at(1);
+        let point = if fraction.is_empty() { "" } else { "." };
+        let exponent_sign = if exponent < 0 { "-" } else { "+" };
+        format!(
+            "{}{}{}{}e{}{}",
+            sign,
+            first,
+            point,
+            fraction,
+            exponent_sign,
+            exponent.abs()
+        )
+    }
+}
+
+// from 2^53, where `f64` can no longer hold every integer and only holds
+// integers, to the end of the range of 64-bit integers
+fn is_large_integer(number: f64) -> bool {
+    (9_007_199_254_740_992.0..18_446_744_073_709_551_616.0).contains(&number.abs())
+}
+
+// round the shortest representation `[-]d[.ddd]e<exp>` to `limit` significant digits
+fn round_significant(formatted: &str, limit: usize, mode: RoundingMode) -> String {
+    let (mantissa, exponent) = formatted
+        .split_once('e')
+        .expect("exponent in scientific notation");
+    let mut exponent: i32 = exponent.parse().expect("integer exponent");
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let mut digits: Vec<u8> = mantissa.bytes().filter(|&byte| byte != b'.').collect();
+    if digits.len() <= limit {
+        return formatted.to_string();
+    }
+
+    let rest = digits.split_off(limit);
+    let round_up = match rest[0] {
+        b'6'..=b'9' => true,
+        b'5' if rest[1..].iter().any(|&digit| digit != b'0') => true,
+        b'5' => match mode {
+            RoundingMode::HalfUp => true,
+            RoundingMode::HalfEven => (digits[limit - 1] - b'0') % 2 == 1,
+        },
+        _ => false,
+    };
+    if round_up {
+        match digits.iter().rposition(|&digit| digit != b'9') {
+            Some(position) => {
+                digits[position] += 1;
+                digits.truncate(position + 1);
+            }
+            // 9.99 rounds to 1e+1
+            None => {
+                digits = vec![b'1'];
+                exponent += 1;
+            }
+        }
+    }
+    while digits.len() > 1 && digits.last() == Some(&b'0') {
+        digits.pop();
+    }
+
+    let digits = String::from_utf8(digits).expect("ASCII digits");
+    let (first, fraction) = digits.split_at(1);
+    if fraction.is_empty() {
+        format!("{}{}e{}", sign, first, exponent)
+    } else {
+        format!("{}{}.{}e{}", sign, first, fraction, exponent)
+    }
+}
+
+/// Rewrite a number in Rust's `{:e}` notation into its canonical form
+///
+/// This is the last step of the canonical number format:
+/// - the exponent marker becomes a capital `E`,
+/// - `+` signs and leading zeros are removed from the exponent, keeping a
+///   single `0` for a zero exponent and the `-` of negative exponents,
+/// - a mantissa without a decimal point gets a `.0` fraction.
+///
+/// The mantissa is otherwise left as it is: digits are neither rounded nor
+/// trimmed, and input without an exponent is returned unchanged.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::normalize_number;
+/// assert_eq!(normalize_number(format!("{:e}", 1.5e300)), "1.5E300");
+/// assert_eq!(normalize_number(format!("{:e}", -0.001)), "-1.0E-3");
+/// assert_eq!(normalize_number("2.5e+007".to_string()), "2.5E7");
+/// assert_eq!(normalize_number("1E+00".to_string()), "1.0E0");
+/// ```
+pub fn normalize_number(input: String) -> String {
+    // https://github.com/gibson042/canonicaljson-go/blob/b9eb21a76/encode.go#L506-L514
+    let (mantissa, exponent) = match input.find(['e', 'E']) {
+        Some(index) => (&input[..index], &input[index + 1..]),
+        None => return input,
+    };
+    let (sign, digits) = match exponent.as_bytes().first() {
+        Some(b'-') => ("-", &exponent[1..]),
+        Some(b'+') => ("", &exponent[1..]),
+        _ => ("", exponent),
+    };
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return input;
+    }
+    let digits = match digits.trim_start_matches('0') {
+        "" => "0",
+        digits => digits,
+    };
+    let fraction = if mantissa.contains('.') { "" } else { ".0" };
+    format!("{}{}E{}{}", mantissa, fraction, sign, digits)
+}
+
+/// Orders keys by their UTF-16 code units, as the spec requires.
+///
+/// This is the order of UTF-8 bytes, except for characters from U+E000 to
+/// U+FFFF, which come after those outside of the BMP (whose surrogates are
+/// U+D800 to U+DFFF).
+pub(crate) fn cmp_utf16(a: &[u8], b: &[u8]) -> Ordering {
+    match (std::str::from_utf8(a), std::str::from_utf8(b)) {
+        (Ok(a), Ok(b)) => a.encode_utf16().cmp(b.encode_utf16()),
+        // keys are always valid UTF-8 once unescaped
+        _ => a.cmp(b),
+    }
+}
+
+/// Replaces the `\u{XXXXX}` escapes written without `surrogate_pairs` by the
+/// characters themselves, for `serde_json` to read the string back.
+fn unescape_braces(serialized: &[u8]) -> String {
+    let serialized = String::from_utf8_lossy(serialized);
+    let mut unescaped = String::with_capacity(serialized.len());
+    let mut rest = &*serialized;
+    while let Some(index) = rest.find('\\') {
+        unescaped.push_str(&rest[..index]);
+        rest = &rest[index..];
+        let len = match brace_escape(rest) {
+            Some((character, len)) => {
+                unescaped.push(character);
+                len
+            }
+            None => {
+                // escaped backslashes are copied whole, so that `\\u{` is left alone
+                let len = if rest.starts_with("\\\\") { 2 } else { 1 };
+                unescaped.push_str(&rest[..len]);
+                len
+            }
+        };
+        rest = &rest[len..];
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// The character of the `\u{XXXXX}` escape `escape` starts with, and the length
+/// of the escape.
+fn brace_escape(escape: &str) -> Option<(char, usize)> {
+    let (hex, _) = escape.strip_prefix("\\u{")?.split_once('}')?;
+    if !hex
+        .bytes()
+        .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+    {
+        return None;
+    }
+    let character = char::from_u32(u32::from_str_radix(hex, 16).ok()?)?;
+    Some((character, hex.len() + "\\u{}".len()))
+}
+
+/// Writes `fragment` escaping quotes, backslashes and control characters, as
+/// well as `DEL` and every non-ASCII character when `ascii_only` is set, and the
+/// byte order mark when `escape_bom` is. Characters outside of the BMP are
+/// written as a surrogate pair, or in braces without `surrogate_pairs`.
+fn write_escaped<W>(writer: &mut W, fragment: &str, options: &Canonicalizer) -> io::Result<()>
+where
+    W: ?Sized + Write,
+{
+    let ascii_only = options.ascii_only;
+    let bytes = fragment.as_bytes();
+    let mut start = 0;
+    for (index, character) in fragment.char_indices() {
+        let escape = match character {
+            '"' => Some(CharEscape::Quote),
+            '\\' => Some(CharEscape::ReverseSolidus),
+            '\n' => Some(CharEscape::LineFeed),
+            '\t' => Some(CharEscape::Tab),
+            '\r' => Some(CharEscape::CarriageReturn),
+            '\u{8}' => Some(CharEscape::Backspace),
+            '\u{c}' => Some(CharEscape::FormFeed),
+            '\u{0}'..='\u{1f}' => Some(CharEscape::AsciiControl(character as u8)),
+            '\u{7f}' if ascii_only => Some(CharEscape::AsciiControl(0x7f)),
+            '\u{feff}' if options.escape_bom => None,
+            _ if ascii_only && !character.is_ascii() => None,
+            _ => continue,
+        };
+        writer.write_all(&bytes[start..index])?;
+        match escape {
+            Some(char_escape) => write_char_escape(writer, char_escape)?,
+            None if !options.surrogate_pairs && u32::from(character) > 0xffff => {
+                write!(writer, "\\u{{{:x}}}", u32::from(character))?;
+            }
+            // UTF-16 code units, i.e. a surrogate pair outside of the BMP
+            None => {
+                for unit in character.encode_utf16(&mut [0; 2]) {
+                    write!(writer, "\\u{:04x}", unit)?;
+                }
+            }
+        }
+        start = index + character.len_utf8();
+    }
+    writer.write_all(&bytes[start..])
+}
+
+/// Serialize a JSON value to String
+///
+/// Accepts any [serde::Serialize] type, so structs deriving `Serialize` need no
+/// intermediate [serde_json::Value], and shared values behind an `Arc` or `Rc`
+/// can be passed without dereferencing them first.
+///
+/// Object members are sorted by the formatter as they are written, whatever
+/// order the `Serialize` implementation emits them in: derived structs, which
+/// emit their fields in declaration order, and `HashMap`s come out sorted just
+/// like a `Value`. Unlike a `Value`, such types can emit the same key twice,
+/// which fails with [CanonicalJSONError::DuplicateKey] by default (see
+/// [Canonicalizer::duplicate_key_policy]).
+///
+/// Only object members are reordered: array elements, `null`s included, are
+/// always written in their original positions, and nothing is ever dropped.
+/// Sets are serialized as arrays, so a `BTreeSet` has a canonical form but a
+/// `HashSet` comes out in whatever order it iterates in.
+///
+/// Malformed input such as the integer `007`, which the spec's test suite knows
+/// as `leading_zero_number`, is rejected by `serde_json` when parsing, and
+/// integers are always written without leading zeros.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::to_string;
+/// # use serde_json::json;
+/// # fn main() {
+///     to_string(&json!(null)); // returns "null"
+///
+///     to_string(&json!("test")); // returns "test"
+///
+///     to_string(&json!(10.0_f64.powf(21.0))); // returns "1.0E21"
+///
+///     to_string(&json!({
+///         "a": "a",
+///         "id": "1",
+///         "b": "b"
+///     })); // returns "{"a":"a","b":"b","id":"1"}"; (orders object keys)
+///
+///     to_string(&json!(vec!["one", "two", "three"])); // returns "["one","two","three"]"
+/// # }
+///
+/// ```
+pub fn to_string<V>(input: &V) -> Result<String, CanonicalJSONError>
+where
+    V: ?Sized + Serialize,
+{
+    Canonicalizer::new().to_string(input)
+}
+
+/// Serialize a JSON value to bytes
+///
+/// The same output as [to_string], without checking that it is valid UTF-8,
+/// for callers that hash or send the bytes straight away.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::to_vec;
+/// # use serde_json::json;
+/// assert_eq!(to_vec(&json!({"b": 1, "a": 2})).unwrap(), br#"{"a":2,"b":1}"#);
+/// ```
+pub fn to_vec<V>(input: &V) -> Result<Vec<u8>, CanonicalJSONError>
+where
+    V: ?Sized + Serialize,
+{
+    Canonicalizer::new().to_vec(input)
+}
+
+/// Serialize a JSON value to a boxed slice
+///
+/// The same bytes as [to_vec], without spare capacity, for canonical forms
+/// that are kept around in large numbers.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::to_boxed_slice;
+/// # use serde_json::json;
+/// let bytes = to_boxed_slice(&json!({"b": 1, "a": 2})).unwrap();
+/// assert_eq!(&*bytes, br#"{"a":2,"b":1}"#);
+/// ```
+pub fn to_boxed_slice<V>(input: &V) -> Result<Box<[u8]>, CanonicalJSONError>
+where
+    V: ?Sized + Serialize,
+{
+    Ok(to_vec(input)?.into_boxed_slice())
+}
+
+/// Serialize a JSON value into a writer
+///
+/// The output goes straight to `writer` as it is produced, except for the
+/// members of objects, which are held until the object is complete to be
+/// sorted. Errors of the writer are reported as [CanonicalJSONError::Io].
+/// Wrap unbuffered writers such as files or sockets in a [std::io::BufWriter].
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::to_writer;
+/// # use serde_json::json;
+/// let mut output = vec![];
+/// to_writer(&mut output, &json!({"b": [1.5], "a": null})).unwrap();
+/// assert_eq!(output, br#"{"a":null,"b":[1.5E0]}"#);
+/// ```
+pub fn to_writer<W, V>(writer: W, input: &V) -> Result<(), CanonicalJSONError>
+where
+    W: Write,
+    V: ?Sized + Serialize,
+{
+    Canonicalizer::new().to_writer(writer, input)
+}
+
+/// Serialize a JSON value into a writer, failing with an [io::Error]
+///
+/// This is [to_writer] for callers that stream into sockets or files and only
+/// deal in `io::Error`s: errors of the writer are returned unchanged, and the
+/// other failures (such as [CanonicalJSONError::DuplicateKey]) are wrapped in
+/// an [io::ErrorKind::InvalidData] error. Prefer [to_writer] to tell those
+/// failures apart, and [to_vec] or [to_string] to collect the output.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::to_writer_io;
+/// # use serde_json::json;
+/// fn send(socket: &mut impl std::io::Write) -> std::io::Result<()> {
+///     to_writer_io(socket, &json!({"b": 1, "a": 2}))
+/// }
+///
+/// let mut output = vec![];
+/// send(&mut output).unwrap();
+/// assert_eq!(output, br#"{"a":2,"b":1}"#);
+/// ```
+pub fn to_writer_io<W, V>(writer: W, input: &V) -> io::Result<()>
+where
+    W: Write,
+    V: ?Sized + Serialize,
+{
+    Ok(to_writer(writer, input)?)
+}
+
+/// Serialize a JSON value to String, enforcing resource limits
+///
+/// Fails with [CanonicalJSONError::MemberLimitExceeded] if any object has more than
+/// `max_members` members, and with [CanonicalJSONError::DepthLimitExceeded] if arrays
+/// and objects are nested more than `max_depth` levels deep. This is a shortcut for
+/// [Canonicalizer::max_members] and [Canonicalizer::max_depth].
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::canonicalize_with_limits;
+/// # use serde_json::json;
+/// assert!(canonicalize_with_limits(&json!({"a": [1]}), 1, 2).is_ok());
+/// assert!(canonicalize_with_limits(&json!({"a": [[1]]}), 1, 2).is_err());
+/// ```
+pub fn canonicalize_with_limits<V>(
+    input: &V,
+    max_members: usize,
+    max_depth: usize,
+) -> Result<String, CanonicalJSONError>
+where
+    V: ?Sized + Serialize,
+{
+    Canonicalizer::new()
+        .max_members(max_members)
+        .max_depth(max_depth)
+        .to_string(input)
+}
+
+/// Serialize a JSON value to String, rewriting its values with `transform`
+/// first
+///
+/// `transform` is applied to a copy of `value`, to every value it holds: the
+/// members of objects and elements of arrays first, then the object or array
+/// itself, and the whole value last. Whatever a call leaves in place is not
+/// visited again.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::canonicalize_with_value_transform;
+/// # use serde_json::{json, Value};
+/// let trim = |value: &mut Value| {
+///     if let Value::String(string) = value {
+///         *string = string.trim().to_string();
+///     }
+/// };
+/// assert_eq!(
+///     canonicalize_with_value_transform(&json!({"b": [" x "], "a": "y\n"}), &trim).unwrap(),
+///     r#"{"a":"y","b":["x"]}"#
+/// );
+/// ```
+pub fn canonicalize_with_value_transform(
+    value: &Value,
+    transform: &dyn Fn(&mut Value),
+) -> Result<String, CanonicalJSONError> {
+    fn apply(value: &mut Value, transform: &dyn Fn(&mut Value)) {
+        match value {
+            Value::Object(map) => map.values_mut().for_each(|value| apply(value, transform)),
+            Value::Array(values) => values.iter_mut().for_each(|value| apply(value, transform)),
+            _ => {}
+        }
+        transform(value);
+    }
+
+    let mut value = value.clone();
+    apply(&mut value, transform);
+    to_string(&value)
+}
+
+/// Members of a JSON object, in the order [to_string] writes them
+///
+/// Anything but an object has no members, and gives an empty list. Member
+/// values are cloned as they are.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::canonical_pairs;
+/// # use serde_json::json;
+/// let pairs = canonical_pairs(&json!({"b": 1, "a": [2]}));
+/// assert_eq!(pairs, vec![("a".to_string(), json!([2])), ("b".to_string(), json!(1))]);
+/// assert!(canonical_pairs(&json!([1, 2])).is_empty());
+/// ```
+pub fn canonical_pairs(value: &Value) -> Vec<(String, Value)> {
+    let mut pairs: Vec<(String, Value)> = match value {
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+        _ => return vec![],
+    };
+    pairs.sort_by(|a, b| cmp_utf16(a.0.as_bytes(), b.0.as_bytes()));
+    pairs
+}
+
+/// Serialize each JSON value of a slice to String
+///
+/// Values are canonicalized independently, stopping at the first error.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::canonicalize_all;
+/// # use serde_json::json;
+/// let records = vec![json!({"id": "2", "a": 1}), json!([true])];
+/// assert_eq!(
+///     canonicalize_all(&records).unwrap(),
+///     vec![r#"{"a":1,"id":"2"}"#, "[true]"]
+/// );
+/// ```
+pub fn canonicalize_all(values: &[Value]) -> Result<Vec<String>, CanonicalJSONError> {
+    let canonicalizer = Canonicalizer::new();
+    // one buffer for all the values, copied out at the exact size of each
+    let mut scratch = vec![];
+    values
+        .iter()
+        .map(|value| {
+            scratch.clear();
+            canonicalizer.to_writer(&mut scratch, value)?;
+            Ok(String::from_utf8(scratch.clone())?)
+        })
+        .collect()
+}
+
+/// Serialize a JSON value and return its canonical bytes as an iterator
+///
+/// Object members have to be sorted before anything is emitted, so the whole
+/// output is buffered first: this is a convenience for byte-oriented pipelines,
+/// not a streaming serializer.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::canonical_bytes;
+/// # use serde_json::json;
+/// let bytes: Vec<u8> = canonical_bytes(&json!({"b": 1, "a": 2})).unwrap().collect();
+/// assert_eq!(bytes, br#"{"a":2,"b":1}"#);
+/// ```
+pub fn canonical_bytes<V>(input: &V) -> Result<impl Iterator<Item = u8>, CanonicalJSONError>
+where
+    V: ?Sized + Serialize,
+{
+    Ok(to_vec(input)?.into_iter())
+}
+
+/// Serialize a JSON value, handing its canonical bytes to `callback` in chunks
+/// of `chunk_size` bytes
+///
+/// Every chunk but the last is exactly `chunk_size` bytes long. Chunks are
+/// handed over as soon as they are full, so a failure may come after some of
+/// them. Panics if `chunk_size` is 0.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::to_chunks;
+/// # use serde_json::json;
+/// let mut chunks = vec![];
+/// to_chunks(&json!({"b": 1, "a": 2}), 4, |chunk| chunks.push(chunk.to_vec())).unwrap();
+/// assert_eq!(chunks, vec![&b"{\"a\""[..], b":2,\"", b"b\":1", b"}"]);
+/// ```
+pub fn to_chunks<V, F>(input: &V, chunk_size: usize, callback: F) -> Result<(), CanonicalJSONError>
+where
+    V: ?Sized + Serialize,
+    F: FnMut(&[u8]),
+{
+    assert!(chunk_size != 0, "chunk size must be non-zero");
+    let mut writer = Chunks {
+        chunk: Vec::with_capacity(chunk_size),
+        chunk_size,
+        callback,
+    };
+    to_writer(&mut writer, input)?;
+    if !writer.chunk.is_empty() {
+        (writer.callback)(&writer.chunk);
+    }
+    Ok(())
+}
+
+/// Hands what is written over to a callback, `chunk_size` bytes at a time.
+struct Chunks<F> {
+    chunk: Vec<u8>,
+    chunk_size: usize,
+    callback: F,
+}
+
+impl<F: FnMut(&[u8])> Write for Chunks<F> {
+    fn write(&mut self, mut buf: &[u8]) -> io::Result<usize> {
+        let written = buf.len();
+        while !buf.is_empty() {
+            let (head, tail) = buf.split_at(buf.len().min(self.chunk_size - self.chunk.len()));
+            self.chunk.extend_from_slice(head);
+            if self.chunk.len() == self.chunk_size {
+                (self.callback)(&self.chunk);
+                self.chunk.clear();
+            }
+            buf = tail;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Parse a JSON document and serialize it to its canonical String
+///
+/// Gives the same output as parsing into a [serde_json::Value] and calling
+/// [to_string], but strings without escapes are borrowed from `input` rather
+/// than copied, and members sharing a key are all kept: they fail with
+/// [CanonicalJSONError::DuplicateKey] (see
+/// [Canonicalizer::duplicate_key_policy]) instead of the last one silently
+/// winning. Trailing content after the document is an error, and so are
+/// documents nested 128 levels deep or more: `serde_json`'s recursion limit
+/// makes them fail with [CanonicalJSONError::JSONError] ("recursion limit
+/// exceeded") rather than overflow the stack.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::canonicalize_slice;
+/// let input = br#"{"title": "I \u2764 testing", "id": "1"}"#;
+/// assert_eq!(
+///     canonicalize_slice(input).unwrap(),
+///     r#"{"id":"1","title":"I ❤ testing"}"#
+/// );
+/// ```
+pub fn canonicalize_slice(input: &[u8]) -> Result<String, CanonicalJSONError> {
+    Canonicalizer::new().canonicalize_slice(input)
+}
+
+/// Whether a JSON document is already in canonical form
+///
+/// Fails with [CanonicalJSONError::JSONError] if `input` is not JSON at all,
+/// and with [CanonicalJSONError::DuplicateKey] if an object repeats a key.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::is_canonical;
+/// assert!(is_canonical(r#"{"a":[1,2.5E0],"b":"é"}"#).unwrap());
+/// assert!(!is_canonical(r#"{"b":"é","a":[1,2.5]}"#).unwrap());
+/// ```
+pub fn is_canonical(input: &str) -> Result<bool, CanonicalJSONError> {
+    Ok(canonical_mismatch(input)?.is_none())
+}
+
+/// Byte offset of the first difference between a JSON document and its
+/// canonical form, or `None` when it is canonical
+///
+/// When one is a prefix of the other, the offset is the length of the shorter.
+/// Fails like [is_canonical].
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::canonical_mismatch;
+/// assert_eq!(canonical_mismatch(r#"{"a":1,"b":2}"#).unwrap(), None);
+/// assert_eq!(canonical_mismatch(r#"{"a":1, "b":2}"#).unwrap(), Some(7));
+/// ```
+pub fn canonical_mismatch(input: &str) -> Result<Option<usize>, CanonicalJSONError> {
+    let canonical = canonicalize(input)?;
+    if canonical == input {
+        return Ok(None);
+    }
+    let common = input
+        .bytes()
+        .zip(canonical.bytes())
+        .take_while(|(a, b)| a == b)
+        .count();
+    Ok(Some(common))
+}
+
+/// Parse a JSON document that must already be in canonical form
+///
+/// Fails with [CanonicalJSONError::NotCanonical], holding the canonical form as
+/// `expected` and `input` as `found`, when serializing the parsed value does not
+/// give back `input` byte for byte: whitespace, even trailing, escapes of
+/// characters that are written as they are, unsorted or repeated keys and
+/// numbers in another form are all rejected.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::from_str;
+/// # use canonical_json::CanonicalJSONError;
+/// # use serde_json::json;
+/// assert_eq!(from_str(r#"{"a":[1,true],"b":"é"}"#).unwrap(), json!({"a": [1, true], "b": "é"}));
+/// assert!(matches!(
+///     from_str(r#"{"b":"\u00e9","a":[1.5E0]}"#),
+///     Err(CanonicalJSONError::NotCanonical { expected, .. }) if expected == r#"{"a":[1.5E0],"b":"é"}"#
+/// ));
+/// ```
+pub fn from_str(input: &str) -> Result<Value, CanonicalJSONError> {
+    let value: Value = serde_json::from_str(input)?;
+    let expected = to_string(&value)?;
+    if expected != input {
+        return Err(CanonicalJSONError::NotCanonical {
+            expected,
+            found: input.to_string(),
+        });
+    }
+    Ok(value)
+}
+
+/// Parse a JSON document that may not be valid UTF-8, returning its canonical
+/// bytes
+///
+/// Invalid sequences are replaced with U+FFFD before parsing, for best-effort
+/// processing of lossy sources. This only helps within strings: anywhere else
+/// the replacement character is still a syntax error.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::canonicalize_slice_lossy;
+/// assert_eq!(
+///     canonicalize_slice_lossy(b"{\"name\": \"caf\xe9\"}").unwrap(),
+///     "{\"name\":\"caf\u{fffd}\"}".as_bytes()
+/// );
+/// ```
+pub fn canonicalize_slice_lossy(input: &[u8]) -> Result<Vec<u8>, CanonicalJSONError> {
+    let input = String::from_utf8_lossy(input);
+    Ok(canonicalize_slice(input.as_bytes())?.into_bytes())
+}
+
+/// Parse a JSON document and return its canonical String
+///
+/// This is [canonicalize_slice] for documents already held as `&str`, e.g. the
+/// body of a request whose signature is to be checked. Syntax errors are
+/// reported as [CanonicalJSONError::JSONError].
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::canonicalize;
+/// assert_eq!(
+///     canonicalize(r#"{"b": [1.50, true], "a": "\u00e9"}"#).unwrap(),
+///     r#"{"a":"é","b":[1.5E0,true]}"#
+/// );
+/// assert!(canonicalize("{\"a\":").is_err());
+/// ```
+pub fn canonicalize(input: &str) -> Result<String, CanonicalJSONError> {
+    canonicalize_slice(input.as_bytes())
+}
+
+/// Parse a JSON document, returning the parsed value along with its canonical
+/// String
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::parse_and_canonicalize;
+/// # use serde_json::json;
+/// let (value, canonical) = parse_and_canonicalize(r#"{"b": 1, "a": 2}"#).unwrap();
+/// assert_eq!(value, json!({"a": 2, "b": 1}));
+/// assert_eq!(canonical, r#"{"a":2,"b":1}"#);
+/// ```
+pub fn parse_and_canonicalize(input: &str) -> Result<(Value, String), CanonicalJSONError> {
+    let value: Value = serde_json::from_str(input)?;
+    let canonical = to_string(&value)?;
+    Ok((value, canonical))
+}
+
+/// Serialize a JSON value into a caller-provided buffer
+///
+/// Returns the length of the canonical output, which occupies the start of
+/// `buf`, or fails with [CanonicalJSONError::BufferOverflow] if it does not fit.
+/// The output itself is not allocated, but object members are still buffered
+/// while they are being sorted.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::to_fixed_buf;
+/// # use serde_json::json;
+/// let mut buf = [0; 16];
+/// let len = to_fixed_buf(&json!({"b": 1, "a": 2}), &mut buf).unwrap();
+/// assert_eq!(&buf[..len], br#"{"a":2,"b":1}"#);
+/// ```
+pub fn to_fixed_buf(value: &Value, buf: &mut [u8]) -> Result<usize, CanonicalJSONError> {
+    let mut writer = FixedBuf { buf, len: 0 };
+    let mut serializer =
+        serde_json::Serializer::with_formatter(&mut writer, CanonicalFormatter::new());
+    value.serialize(&mut serializer)?;
+    Ok(writer.len)
+}
+
+/// Writes into a slice, reporting [CanonicalJSONError::BufferOverflow] when full.
+struct FixedBuf<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for FixedBuf<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let end = self.len + buf.len();
+        if end > self.buf.len() {
+            return Err(formatter_error(CanonicalJSONError::BufferOverflow(
+                self.buf.len(),
+            )));
+        }
+        self.buf[self.len..end].copy_from_slice(buf);
+        self.len = end;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Append the canonical form of a JSON value to a String
+///
+/// The output is pushed onto `out` as it is produced, without going through a
+/// separate buffer to be checked as a whole. On error, `out` is left as it was.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::to_string_into;
+/// # use serde_json::json;
+/// let mut out = String::from("records: ");
+/// to_string_into(&mut out, &json!({"b": 1, "a": 2})).unwrap();
+/// assert_eq!(out, r#"records: {"a":2,"b":1}"#);
+/// ```
+pub fn to_string_into(out: &mut String, value: &Value) -> Result<(), CanonicalJSONError> {
+    let len = out.len();
+    let result = to_writer(StringWriter(out), value);
+    if result.is_err() {
+        out.truncate(len);
+    }
+    result
+}
+
+/// Pushes what is written onto a String, chunk by chunk.
+///
+/// `serde_json` only writes through [Write], so every chunk is checked as
+/// UTF-8 on its own before being pushed; the `string_heavy` benchmark puts
+/// this within noise of [to_string] checking the whole output once. The
+/// formatter only ever writes whole characters, so a chunk that is not valid
+/// UTF-8 fails with [CanonicalJSONError::Utf8Error] rather than being held
+/// until the rest of its characters arrive; the chunk is only copied to build
+/// that error.
+struct StringWriter<'a>(&'a mut String);
+
+impl Write for StringWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match std::str::from_utf8(buf) {
+            Ok(text) => self.0.push_str(text),
+            Err(_) => {
+                let error = String::from_utf8(buf.to_vec()).unwrap_err();
+                return Err(formatter_error(CanonicalJSONError::Utf8Error(error)));
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Serialize a value to String, also returning how many non-finite numbers
+/// were written as `null`
+///
+/// A [serde_json::Value] cannot hold `NaN` nor infinities, so the count is only
+/// ever non-zero for other `Serialize` types, whose `f32` and `f64` values are
+/// counted. `serde_json` writes those as `null` without showing them to the
+/// formatter, so `input` is serialized twice: once to count them, once to
+/// canonicalize it.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::to_string_counting_non_finite;
+/// let (serialized, count) = to_string_counting_non_finite(&[1.5, f64::NAN]).unwrap();
+/// assert_eq!(serialized, "[1.5E0,null]");
+/// assert_eq!(count, 1);
+/// ```
+pub fn to_string_counting_non_finite<V>(input: &V) -> Result<(String, usize), CanonicalJSONError>
+where
+    V: ?Sized + Serialize,
+{
+    let count = count_non_finite(input)?;
+    Ok((to_string(input)?, count))
+}
+
+/// Length in bytes of the canonical form of a JSON value
+///
+/// The output is counted as it is produced and then discarded, so that nothing
+/// but the members of objects being sorted is held in memory.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::canonical_len;
+/// # use serde_json::json;
+/// assert_eq!(canonical_len(&json!({"b": 1, "a": [1.5]})).unwrap(), 19);
+/// ```
+pub fn canonical_len<V>(input: &V) -> Result<usize, CanonicalJSONError>
+where
+    V: ?Sized + Serialize,
+{
+    let mut tally = Tally::new(io::sink());
+    let mut serializer =
+        serde_json::Serializer::with_formatter(&mut tally, CanonicalFormatter::new());
+    input.serialize(&mut serializer)?;
+    Ok(tally.bytes)
+}
+
+/// Serialize a JSON value to two writers at once
+///
+/// Every byte of the canonical output is written to `first`, then to `second`,
+/// e.g. to store a document while computing its digest. Fails with
+/// [CanonicalJSONError::Io] as soon as either of them fails.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::ser::to_writer_tee;
+/// # use serde_json::json;
+/// let (mut stored, mut hashed) = (vec![], vec![]);
+/// to_writer_tee(&mut stored, &mut hashed, &json!({"b": 1, "a": 2})).unwrap();
+/// assert_eq!(stored, br#"{"a":2,"b":1}"#);
+/// assert_eq!(stored, hashed);
+/// ```
+pub fn to_writer_tee<W1, W2, V>(first: W1, second: W2, input: &V) -> Result<(), CanonicalJSONError>
+where
+    W1: Write,
+    W2: Write,
+    V: ?Sized + Serialize,
+{
+    let mut serializer =
+        serde_json::Serializer::with_formatter(Tee { first, second }, CanonicalFormatter::new());
+    input.serialize(&mut serializer)?;
+    Ok(())
+}
+
+/// Writes everything to both of its writers.
+struct Tee<W1, W2> {
+    first: W1,
+    second: W2,
+}
+
+impl<W1: Write, W2: Write> Write for Tee<W1, W2> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.first.write_all(buf)?;
+        self.second.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.first.flush()?;
+        self.second.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        canonical_bytes, canonical_len, canonicalize_all, canonicalize_slice,
+        parse_and_canonicalize, to_fixed_buf, to_string, to_vec, to_writer, to_writer_tee,
+        CanonicalFormatter,
+    };
+    use proptest::prelude::*;
+    use serde_json::{json, Value};
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    macro_rules! test_canonical_json {
+        ($v:tt, $e:expr) => {
+            match to_string(&json!($v)) {
+                Ok(serialized_string) => {
+                    println!("serialized is {}", serialized_string);
+                    assert_eq!(serialized_string, $e)
+                },
+                Err(error) => { panic!("error serializing input : {:?}", error) }
+            };
+        };
+    }
+
+    #[test]
+    fn test_to_string() {
+        test_canonical_json!(null, "null");
+        test_canonical_json!((f64::NAN), "null");
+        test_canonical_json!((f64::INFINITY), "null");
+        test_canonical_json!((f64::NEG_INFINITY), "null");
+        test_canonical_json!(true, "true");
+        test_canonical_json!(false, "false");
+        test_canonical_json!(0, "0");
+        test_canonical_json!(123, "123");
+        test_canonical_json!((-123), "-123");
+        test_canonical_json!(23.1, "2.31E1");
+        test_canonical_json!(23, "23");
+        test_canonical_json!(1_f64, "1.0E0");
+        test_canonical_json!(0_f64, "0.0E0");
+        test_canonical_json!((-0.0_f64), "0.0E0");
+        test_canonical_json!(1.5_f32, "1.5E0");
+        test_canonical_json!(23.0, "2.3E1");
+        test_canonical_json!((-23.0), "-2.3E1");
+        test_canonical_json!(2300, "2300");
+        test_canonical_json!(0.00099, "9.9E-4");
+        test_canonical_json!(0.000011, "1.1E-5");
+        test_canonical_json!(0.0000011, "1.1E-6");
+        test_canonical_json!(0.000001, "1.0E-6");
+        test_canonical_json!(5.6, "5.6E0");
+        test_canonical_json!(0.00000099, "9.9E-7");
+        test_canonical_json!(0.0000001, "1.0E-7");
+        test_canonical_json!(0.000000930258908, "9.30258908E-7");
+        test_canonical_json!(0.00000000000068272, "6.8272E-13");
+        test_canonical_json!((10.000_f64.powf(21.0)), "1.0E21");
+        test_canonical_json!((10.0_f64.powi(20)), "1.0E20");
+        test_canonical_json!((10.0_f64.powi(15) + 0.1), "1.0000000000000001E15");
+        test_canonical_json!((10.0_f64.powi(16) * 1.1), "11000000000000000");
+
+        // serialize string
+        test_canonical_json!("", r#""""#);
+        //escape quotes
+        test_canonical_json!(
+            " Preserve single quotes'in string",
+            r#"" Preserve single quotes'in string""#
+        );
+        test_canonical_json!(" Escapes quotes \" ", r#"" Escapes quotes \" ""#);
+        test_canonical_json!("test", r#""test""#);
+        // escapes backslashes
+        test_canonical_json!("This\\and this", r#""This\\and this""#);
+        // unicode characters are written as they are
+        test_canonical_json!("I ❤ testing", "\"I ❤ testing\"");
+
+        // serialize does not alter certain strings (newline, tab, carriagereturn, forwardslashes)
+        test_canonical_json!("This is a sentence.\n", r#""This is a sentence.\n""#);
+        test_canonical_json!("This is a \t tab.", r#""This is a \t tab.""#);
+        test_canonical_json!(
+            "This is a \r carriage return char.",
+            r#""This is a \r carriage return char.""#
+        );
+        test_canonical_json!("image/jpeg", r#""image/jpeg""#);
+        test_canonical_json!("image//jpeg", r#""image//jpeg""#);
+        // serialize preserves scientific notation number within string
+        test_canonical_json!("frequency at 10.0e+04", r#""frequency at 10.0e+04""#);
+        // serialize preserves invalid unicode escape sequence
+        test_canonical_json!("I \\u{} testing", r#""I \\u{} testing""#);
+        // serialize preserves opening curly brackets when invalid unicode escape sequence
+        test_canonical_json!("I \\u{1234 testing", r#""I \\u{1234 testing""#);
+        test_canonical_json!("I \\u{{12345}} testing", r#""I \\u{{12345}} testing""#);
+
+        // outside of the BMP too, lowest and highest included
+        test_canonical_json!("𝄞", "\"𝄞\"");
+        test_canonical_json!("𝗠𝗼𝘇", "\"𝗠𝗼𝘇\"");
+        test_canonical_json!("\u{10000} \u{10FFFF}", "\"\u{10000} \u{10FFFF}\"");
+
+        // serialize object
+        test_canonical_json!(
+            {
+                "a": {},
+                "b": "b"
+            },
+            r#"{"a":{},"b":"b"}"#
+        );
+
+        // serialize object with keys ordered
+        test_canonical_json!(
+            {
+                "a": "a",
+                "id": "1",
+                "b": "b"
+            },
+            r#"{"a":"a","b":"b","id":"1"}"#
+        );
+
+        // serialize deeply nested objects
+        test_canonical_json!(
+            {
+                "a": json!({
+                    "b": "b",
+                    "a": "a",
+                    "c": json!({
+                        "b": "b",
+                        "a": "a",
+                        "c": ["b", "a", "c"],
+                        "d": json!({ "b": "b", "a": "a" }),
+                        "id": "1",
+                        "e": 1,
+                        "f": [2, 3, 1],
+                        "g": json!({
+                            "2": 2,
+                            "3": 3,
+                            "1": json!({
+                                "b": "b",
+                                "a": "a",
+                                "c": "c",
+                            })
+                        })
+                    })
+                }),
+                "id": "1"
+            },
+            concat!(
+                r#"{"a":{"a":"a","b":"b","c":{"a":"a","b":"b","c":["b","a","c"],"#,
+                r#""d":{"a":"a","b":"b"},"e":1,"f":[2,3,1],"#,
+                r#""g":{"1":{"a":"a","b":"b","c":"c"},"2":2,"3":3},"id":"1"}},"id":"1"}"#
+            )
+        );
+
+        test_canonical_json!(
+            {
+                "b": vec!["two", "three"],
+                "a": vec!["zero", "one"]
+            },
+            r#"{"a":["zero","one"],"b":["two","three"]}"#
+        );
 
         test_canonical_json!(
             {
@@ -386,53 +2538,2138 @@ mod tests {
             r#"{"a":{"a":"a","b":"b"},"b":{"c":"c","d":"d"}}"#
         );
 
-        // escapes unicode characters in object keys
-        test_canonical_json!({"é": "check"}, r#"{"\u00e9":"check"}"#);
+        // escapes unicode characters in object keys
+        test_canonical_json!({"é": "check"}, r#"{"é":"check"}"#);
+
+        // the empty key sorts first, at any depth
+        test_canonical_json!({"a": "w", "": "v"}, r#"{"":"v","a":"w"}"#);
+        test_canonical_json!({"x": {"a": "w", "": "v"}}, r#"{"x":{"":"v","a":"w"}}"#);
+        test_canonical_json!(
+            {"x": [{"b": {"a": 1, "": 0}, "": []}]},
+            r#"{"x":[{"":[],"b":{"":0,"a":1}}]}"#
+        );
+
+        // numeric string keys sort as strings, number values keep number rules
+        test_canonical_json!({"7": 7, "42": 42}, r#"{"42":42,"7":7}"#);
+        test_canonical_json!({"7": 7.0, "42": "42"}, r#"{"42":"42","7":7.0E0}"#);
+
+        test_canonical_json!(
+            {
+                "def": "bar",
+                "abc": json!(0.000000930258908),
+                "ghi": json!(1000000000000000000000.0_f64),
+                "rust": "❤",
+                "zoo": [
+                    "zorilla",
+                    "anteater"
+                ]
+            },
+            r#"{"abc":9.30258908E-7,"def":"bar","ghi":1.0E21,"rust":"❤","zoo":["zorilla","anteater"]}"#
+        );
+
+        // serialize empty array
+        test_canonical_json!([], "[]");
+
+        // serialize array should preserve array order
+        test_canonical_json!((vec!["one", "two", "three"]), r#"["one","two","three"]"#);
+
+        // non-ASCII characters are not escaped
+        test_canonical_json!((vec![json!({ "key": "✓" })]), r#"[{"key":"✓"}]"#);
+        test_canonical_json!((vec![json!({ "key": "ę" })]), r#"[{"key":"ę"}]"#);
+        test_canonical_json!((vec![json!({ "key": "é" })]), r#"[{"key":"é"}]"#);
+
+        // serialize array preserves data
+        test_canonical_json!(
+            (vec![
+                json!({ "foo": "bar", "last_modified": "12345", "id": "1" }),
+                json!({ "bar": "baz", "last_modified": "45678", "id": "2" }),
+            ]),
+            r#"[{"foo":"bar","id":"1","last_modified":"12345"},{"bar":"baz","id":"2","last_modified":"45678"}]"#
+        );
+
+        // serialize does not add space separators
+        test_canonical_json!(
+            (vec![
+                json!({ "foo": "bar", "last_modified": "12345", "id": "1" }),
+                json!({ "bar": "baz", "last_modified": "45678", "id": "2" }),
+            ]),
+            r#"[{"foo":"bar","id":"1","last_modified":"12345"},{"bar":"baz","id":"2","last_modified":"45678"}]"#
+        );
+    }
+
+    #[test]
+    fn test_mantissa_fraction() {
+        // floats such as those of the spec's `1.integer` and `2.fraction`
+        // groups: the mantissa always has a fractional digit, and no more zeros
+        // than that one
+        for &(value, expected) in &[
+            (1.0, "1.0E0"),
+            (-1.0, "-1.0E0"),
+            (10.0, "1.0E1"),
+            (100.0, "1.0E2"),
+            (1e21, "1.0E21"),
+            (0.01, "1.0E-2"),
+            (0.1, "1.0E-1"),
+            (0.5, "5.0E-1"),
+            (-0.25, "-2.5E-1"),
+            (1.25, "1.25E0"),
+            (120.0, "1.2E2"),
+        ] {
+            assert_eq!(to_string(&json!(value)).unwrap(), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_array_nulls_keep_their_position() {
+        test_canonical_json!([null, null, 1, null], "[null,null,1,null]");
+        test_canonical_json!([null], "[null]");
+        test_canonical_json!([1, null, null, null, 2], "[1,null,null,null,2]");
+        test_canonical_json!(
+            [{ "b": null, "a": null }, null, [null, {}]],
+            r#"[{"a":null,"b":null},null,[null,{}]]"#
+        );
+        test_canonical_json!({ "z": [null, 3, null], "a": null }, r#"{"a":null,"z":[null,3,null]}"#);
+        assert_eq!(to_string_generic(&[None, Some(2), None]), "[null,2,null]");
+    }
+
+    #[test]
+    fn test_quotes_and_backslashes() {
+        test_canonical_json!("it's", r#""it's""#);
+        test_canonical_json!("'\"'", r#""'\"'""#);
+        test_canonical_json!("\\'\\\"", r#""\\'\\\"""#);
+        test_canonical_json!("\\u{41}", r#""\\u{41}""#);
+        test_canonical_json!("\\u00e9 é", r#""\\u00e9 é""#);
+        test_canonical_json!({ "'\\\"": "\"'" }, r#"{"'\\\"":"\"'"}"#);
+
+        // fragments are escaped even when the caller did not split them
+        let mut formatter = super::Canonicalizer::new().ascii_only(true).formatter();
+        let mut bytes = vec![];
+        serde_json::ser::Formatter::write_string_fragment(
+            &mut formatter,
+            &mut bytes,
+            "'\"\\\n\u{1}\u{7f}🦀",
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            r#"'\"\\\n\u0001\u007f\ud83e\udd80"#
+        );
+    }
+
+    #[test]
+    fn test_rounding_mode() {
+        use super::{Canonicalizer, RoundingMode};
+
+        let round = |value: f64, digits, mode| {
+            Canonicalizer::new()
+                .max_significant_digits(digits)
+                .rounding_mode(mode)
+                .to_string(&json!(value))
+                .unwrap()
+        };
+        for &(value, digits, half_even, half_up) in &[
+            // exactly on the boundary
+            (0.125, 2, "1.2E-1", "1.3E-1"),
+            (0.375, 2, "3.8E-1", "3.8E-1"),
+            (2.5, 1, "2.0E0", "3.0E0"),
+            (-2.5, 1, "-2.0E0", "-3.0E0"),
+            (9.5, 1, "1.0E1", "1.0E1"),
+            // off the boundary, both modes agree
+            (0.1251, 2, "1.3E-1", "1.3E-1"),
+            (0.1249, 2, "1.2E-1", "1.2E-1"),
+            (99.96, 3, "1.0E2", "1.0E2"),
+            (1.5, 3, "1.5E0", "1.5E0"),
+            (1.205, 3, "1.2E0", "1.21E0"),
+        ] {
+            assert_eq!(
+                round(value, digits, RoundingMode::HalfEven),
+                half_even,
+                "{}",
+                value
+            );
+            assert_eq!(
+                round(value, digits, RoundingMode::HalfUp),
+                half_up,
+                "{}",
+                value
+            );
+        }
+
+        // integers are not rounded
+        let canonicalizer = Canonicalizer::new().max_significant_digits(1);
+        assert_eq!(canonicalizer.to_string(&json!(12345)).unwrap(), "12345");
+    }
+
+    #[test]
+    fn test_integer_boundaries() {
+        use super::{Canonicalizer, NumberFormat};
+
+        // 2^53 + 1 is the first integer an f64 cannot hold
+        let value = json!([
+            i64::MIN,
+            i64::MAX,
+            u64::MAX,
+            0,
+            -1,
+            9007199254740993u64,
+            -9007199254740993i64
+        ]);
+        let expected = "[-9223372036854775808,9223372036854775807,18446744073709551615,0,-1,9007199254740993,-9007199254740993]";
+        assert_eq!(to_string(&value).unwrap(), expected);
+        assert_eq!(canonicalize_slice(expected.as_bytes()).unwrap(), expected);
+        // integers never go through the float path, where these options apply
+        for canonicalizer in &[
+            Canonicalizer::new().max_significant_digits(1),
+            Canonicalizer::new().number_format(NumberFormat::Legacy),
+            Canonicalizer::new()
+                .plain_decimals(true)
+                .plain_decimal_exponents(0..=0),
+        ] {
+            assert_eq!(canonicalizer.to_string(&value).unwrap(), expected);
+        }
+        assert_eq!(
+            to_string_generic(&(i128::MIN, u128::MAX)),
+            "[-170141183460469231731687303715884105728,340282366920938463463374607431768211455]"
+        );
+    }
+
+    #[test]
+    fn test_large_integral_floats() {
+        for &(value, expected) in &[
+            (2.0_f64.powi(53), "9007199254740992"),
+            (2.0_f64.powi(53) + 2.0, "9007199254740994"),
+            (-(2.0_f64.powi(53)), "-9007199254740992"),
+            (2.0_f64.powi(62), "4611686018427387904"),
+            (2.0_f64.powi(64) - 2048.0, "18446744073709549568"),
+            // outside of the range, the exponent form stays
+            (2.0_f64.powi(53) - 1.0, "9.007199254740991E15"),
+            (2.0_f64.powi(64), "1.8446744073709552E19"),
+            (1e21, "1.0E21"),
+        ] {
+            assert_eq!(to_string(&json!(value)).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_plain_decimals() {
+        use super::{Canonicalizer, NumberFormat};
+
+        let plain = Canonicalizer::new().plain_decimals(true);
+        let to_plain = |canonicalizer: &Canonicalizer, value: f64| {
+            canonicalizer.to_string(&json!(value)).unwrap()
+        };
+        for &(value, expected) in &[
+            (1e-7, "0.0000001"),
+            (-1.5e-7, "-0.00000015"),
+            (0.5, "0.5"),
+            (1.0, "1"),
+            (-0.0, "0"),
+            (123.456, "123.456"),
+            (1e20, "100000000000000000000"),
+            (1.7976931348623157e20, "179769313486231570000"),
+        ] {
+            assert_eq!(to_plain(&plain, value), expected, "{}", value);
+        }
+        // outside of the range, the exponential notation is kept
+        for &(value, expected) in &[
+            (1e-8, "1.0E-8"),
+            (-2.5e-300, "-2.5E-300"),
+            (1e21, "1.0E21"),
+            (f64::MAX, "1.7976931348623157E308"),
+        ] {
+            assert_eq!(to_plain(&plain, value), expected, "{}", value);
+        }
+
+        let narrow = plain.clone().plain_decimal_exponents(-2..=2);
+        assert_eq!(to_plain(&narrow, 0.01), "0.01");
+        assert_eq!(to_plain(&narrow, 0.001), "1.0E-3");
+        assert_eq!(to_plain(&narrow, 999.0), "999");
+        assert_eq!(to_plain(&narrow, 1000.5), "1.0005E3");
+        let legacy = narrow.number_format(NumberFormat::Legacy);
+        assert_eq!(to_plain(&legacy, 0.001), "0.001");
+        assert_eq!(to_plain(&legacy, 1e-7), "1e-7");
+    }
+
+    #[test]
+    fn test_legacy_number_format() {
+        use super::{Canonicalizer, NumberFormat};
+
+        let legacy = Canonicalizer::new().number_format(NumberFormat::Legacy);
+        for &(value, expected) in &[
+            (10.0_f64.powf(21.0), "1e+21"),
+            (10.0_f64.powf(20.0), "100000000000000000000"),
+            (1.5, "1.5"),
+            (-1.5, "-1.5"),
+            (123.0, "123"),
+            (0.0, "0"),
+            (-0.0, "0"),
+            (0.000001, "0.000001"),
+            (0.0000001, "1e-7"),
+            (-1.25e-10, "-1.25e-10"),
+            (1.7976931348623157e308, "1.7976931348623157e+308"),
+            (9.30258908e-7, "9.30258908e-7"),
+            (123456.789, "123456.789"),
+        ] {
+            assert_eq!(legacy.to_string(&json!(value)).unwrap(), expected);
+        }
+        // only floating point numbers are affected
+        assert_eq!(legacy.to_string(&json!([1, 2.5])).unwrap(), "[1,2.5]");
+
+        // the default stays the spec's form
+        assert_eq!(to_string(&json!(10.0_f64.powf(21.0))).unwrap(), "1.0E21");
+    }
+
+    #[test]
+    fn test_ascii_only() {
+        use super::Canonicalizer;
+
+        let value = json!({ "名前": "we ❤ Rust 🦀", "b": "\u{7f}\t" });
+        let escaped = Canonicalizer::new()
+            .ascii_only(true)
+            .to_string(&value)
+            .unwrap();
+        assert!(escaped.is_ascii());
+        assert_eq!(
+            escaped,
+            r#"{"b":"\u007f\t","\u540d\u524d":"we \u2764 Rust \ud83e\udd80"}"#
+        );
+
+        let literal = Canonicalizer::new()
+            .ascii_only(false)
+            .to_string(&value)
+            .unwrap();
+        assert_eq!(literal, "{\"b\":\"\u{7f}\\t\",\"名前\":\"we ❤ Rust 🦀\"}");
+    }
+
+    #[test]
+    fn test_escape_bom() {
+        use super::Canonicalizer;
+
+        let value = json!({ "\u{feff}key": "\u{feff}value é" });
+        let literal = Canonicalizer::new().ascii_only(false);
+        assert_eq!(
+            literal.to_string(&value).unwrap(),
+            "{\"\u{feff}key\":\"\u{feff}value é\"}"
+        );
+        assert_eq!(
+            literal.escape_bom(true).to_string(&value).unwrap(),
+            r#"{"\ufeffkey":"\ufeffvalue é"}"#
+        );
+        assert_eq!(
+            Canonicalizer::new()
+                .ascii_only(true)
+                .escape_bom(true)
+                .to_string(&value)
+                .unwrap(),
+            r#"{"\ufeffkey":"\ufeffvalue \u00e9"}"#
+        );
+    }
+
+    #[test]
+    fn test_surrogate_pairs() {
+        use super::Canonicalizer;
+
+        let value = json!({ "🦀": "é 😀 \u{10ffff}", "\\u{1f980}": 1, "😀": 2 });
+        let ascii = Canonicalizer::new().ascii_only(true);
+        assert_eq!(
+            ascii.to_string(&value).unwrap(),
+            r#"{"\\u{1f980}":1,"\ud83d\ude00":2,"\ud83e\udd80":"\u00e9 \ud83d\ude00 \udbff\udfff"}"#
+        );
+        assert_eq!(
+            ascii.surrogate_pairs(false).to_string(&value).unwrap(),
+            r#"{"\\u{1f980}":1,"\u{1f600}":2,"\u{1f980}":"\u00e9 \u{1f600} \u{10ffff}"}"#
+        );
+        // nothing is escaped without `ascii_only`
+        assert_eq!(
+            Canonicalizer::new()
+                .ascii_only(false)
+                .surrogate_pairs(false)
+                .to_string(&value)
+                .unwrap(),
+            "{\"\\\\u{1f980}\":1,\"😀\":2,\"🦀\":\"é 😀 \u{10ffff}\"}"
+        );
+    }
+
+    #[test]
+    fn test_astral_characters_escaped_as_surrogate_pairs() {
+        use super::Canonicalizer;
+
+        let ascii = Canonicalizer::new().ascii_only(true);
+        for &(input, expected) in &[
+            ("\u{1f600}", r#""\ud83d\ude00""#),
+            ("\u{1d11e}", r#""\ud834\udd1e""#),
+            (
+                "a é \u{1f600} \u{ffff} \u{1d11e}!",
+                r#""a \u00e9 \ud83d\ude00 \uffff \ud834\udd1e!""#,
+            ),
+        ] {
+            let serialized = ascii.to_string(&json!(input)).unwrap();
+            assert_eq!(serialized, expected);
+            assert_eq!(serde_json::from_str::<String>(&serialized).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn test_sort_key_normalization() {
+        use super::{Canonicalizer, NfKind};
+
+        // U+FB01 LATIN SMALL LIGATURE FI folds to "fi" under NFKC only
+        let value = json!({ "g": 1, "\u{fb01}": 2 });
+        let sort = |kind| {
+            Canonicalizer::new()
+                .ascii_only(false)
+                .sort_key_normalization(kind)
+                .to_string(&value)
+                .unwrap()
+        };
+        assert_eq!(sort(NfKind::None), "{\"g\":1,\"\u{fb01}\":2}");
+        assert_eq!(sort(NfKind::Nfc), "{\"g\":1,\"\u{fb01}\":2}");
+        assert_eq!(sort(NfKind::Nfkc), "{\"\u{fb01}\":2,\"g\":1}");
+
+        // U+212B ANGSTROM SIGN composes to U+00C5, which sorts before U+00D0
+        let value = json!({ "\u{d0}": 1, "\u{212b}": 2 });
+        let sorted = Canonicalizer::new()
+            .sort_key_normalization(NfKind::Nfc)
+            .to_string(&value)
+            .unwrap();
+        assert_eq!(sorted, "{\"\u{212b}\":2,\"\u{d0}\":1}");
+
+        // keys with the same normal form fall back to their raw order
+        let value = json!({ "\u{e9}": 1, "e\u{301}": 2 });
+        let sorted = Canonicalizer::new()
+            .sort_key_normalization(NfKind::Nfc)
+            .to_string(&value)
+            .unwrap();
+        assert_eq!(sorted, "{\"e\u{301}\":2,\"\u{e9}\":1}");
+    }
+
+    #[test]
+    fn test_sort_key_normalization_ties() {
+        use super::{Canonicalizer, NfKind};
+        use serde::Serialize;
+
+        // "\u{fb01}" and "fi" compare equal under NFKC, "\u{e9}" and
+        // "e\u{301}" under both NFC and NFKC
+        let keys = ["\u{fb01}", "fi", "\u{e9}", "e\u{301}", "a"];
+        let canonicalize = |kind, members: Vec<(String, Value)>| {
+            let formatter = Canonicalizer::new()
+                .ascii_only(true)
+                .sort_key_normalization(kind)
+                .formatter();
+            let mut serializer = serde_json::Serializer::with_formatter(vec![], formatter);
+            Members(members).serialize(&mut serializer).unwrap();
+            String::from_utf8(serializer.into_inner()).unwrap()
+        };
+        for &(kind, expected) in &[
+            (
+                NfKind::Nfc,
+                r#"{"a":4,"fi":1,"e\u0301":3,"\u00e9":2,"\ufb01":0}"#,
+            ),
+            (
+                NfKind::Nfkc,
+                r#"{"a":4,"fi":1,"\ufb01":0,"e\u0301":3,"\u00e9":2}"#,
+            ),
+        ] {
+            // whatever order the members are serialized in
+            for rotation in 0..keys.len() {
+                let mut members: Vec<_> = keys
+                    .iter()
+                    .enumerate()
+                    .map(|(index, key)| (key.to_string(), json!(index)))
+                    .collect();
+                members.rotate_left(rotation);
+                assert_eq!(canonicalize(kind, members.clone()), expected);
+                members.reverse();
+                assert_eq!(canonicalize(kind, members), expected);
+            }
+        }
+    }
+
+    /// Accepts a single byte per call.
+    struct OneByteAtATime(Vec<u8>);
+
+    impl std::io::Write for OneByteAtATime {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.extend(buf.first());
+            Ok(buf.len().min(1))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn to_string_one_byte_at_a_time(input: &Value) -> String {
+        use serde::Serialize;
+
+        let writer = OneByteAtATime(vec![]);
+        let mut serializer =
+            serde_json::Serializer::with_formatter(writer, CanonicalFormatter::new());
+        input.serialize(&mut serializer).unwrap();
+        String::from_utf8(serializer.into_inner().0).unwrap()
+    }
+
+    #[test]
+    fn test_char_escapes_with_short_writes() {
+        for value in &[
+            json!("\"\\/\u{8}\u{c}\n\r\t\u{0}\u{1f}\u{7f}"),
+            json!(["\n", { "\t": "\u{1}" }]),
+        ] {
+            assert_eq!(
+                to_string_one_byte_at_a_time(value),
+                to_string(value).unwrap()
+            );
+        }
+        assert_eq!(
+            to_string_one_byte_at_a_time(&json!("\"\\\n\u{1}")),
+            r#""\"\\\n\u0001""#
+        );
+    }
+
+    #[test]
+    fn test_large_string_with_short_writes() {
+        let large: String = "I ❤ \"Rust\" 🦀\\\n plain ascii ".repeat(2000);
+        let value = json!(large);
+        let serialized = to_string_one_byte_at_a_time(&value);
+        assert_eq!(serialized, to_string(&value).unwrap());
+        assert_eq!(
+            serialized.len(),
+            2 + 2000 * r#"I ❤ \"Rust\" 🦀\\\n plain ascii "#.len()
+        );
+        assert_eq!(serde_json::from_str::<String>(&serialized).unwrap(), large);
+    }
+
+    /// Accepts a varying number of bytes per call, and is now and then
+    /// interrupted, like a busy socket.
+    struct Trickle {
+        written: Vec<u8>,
+        calls: usize,
+    }
+
+    impl std::io::Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.calls += 1;
+            if self.calls.is_multiple_of(5) {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            let accepted = buf.len().min(self.calls % 7 + 1);
+            self.written.extend_from_slice(&buf[..accepted]);
+            Ok(accepted)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_partial_writes_deliver_everything() {
+        use super::{Canonicalizer, NumberFormat};
+        use serde::Serialize;
+
+        let value = json!({
+            "records": (0..50).map(|i| json!({
+                "id": format!("record-{}", i),
+                "title": "I ❤ \"testing\" 🦀\n",
+                "score": i as f64 * 1.25,
+                "count": i,
+                "tags": ["\u{1}", "é", null, true],
+            })).collect::<Vec<_>>(),
+            "numbers": [1e21, -0.0, 2.0_f64.powi(60), 9.30258908e-7],
+            "\"": {},
+        });
+        for canonicalizer in &[
+            Canonicalizer::new(),
+            Canonicalizer::new()
+                .ascii_only(true)
+                .number_format(NumberFormat::Legacy),
+        ] {
+            let mut writer = Trickle {
+                written: vec![],
+                calls: 0,
+            };
+            let mut serializer =
+                serde_json::Serializer::with_formatter(&mut writer, canonicalizer.formatter());
+            value.serialize(&mut serializer).unwrap();
+            assert_eq!(
+                String::from_utf8(writer.written).unwrap(),
+                canonicalizer.to_string(&value).unwrap()
+            );
+        }
+    }
+
+    /// Accepts a few bytes, then fails like a closed socket.
+    struct BrokenPipe {
+        remaining: usize,
+    }
+
+    impl std::io::Write for BrokenPipe {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.remaining == 0 {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+            let written = buf.len().min(self.remaining);
+            self.remaining -= written;
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_canonical_formatter_in_a_pipeline() {
+        use serde::Serialize;
+
+        // a formatter can be cloned before use, and built under its former name
+        let formatter = CanonicalFormatter::default();
+        #[allow(deprecated)]
+        let renamed: super::JsonFormatter = formatter.clone();
+        for formatter in [formatter, renamed] {
+            let mut serializer = serde_json::Serializer::with_formatter(vec![], formatter);
+            json!({ "b": 1, "a": [2.5] })
+                .serialize(&mut serializer)
+                .unwrap();
+            assert_eq!(serializer.into_inner(), br#"{"a":[2.5E0],"b":1}"#);
+        }
+    }
+
+    #[test]
+    fn test_io_error_kind() {
+        use super::CanonicalJSONError;
+        use serde::Serialize;
+
+        let value = json!({ "b": "b", "a": [1, 2, 3] });
+        let mut serializer = serde_json::Serializer::with_formatter(
+            BrokenPipe { remaining: 5 },
+            CanonicalFormatter::new(),
+        );
+        let error = CanonicalJSONError::from(value.serialize(&mut serializer).unwrap_err());
+
+        assert!(matches!(error, CanonicalJSONError::Io(_)));
+        assert_eq!(error.io_kind(), Some(std::io::ErrorKind::BrokenPipe));
+        assert_eq!(CanonicalJSONError::DepthLimitExceeded(1).io_kind(), None);
+    }
+
+    #[test]
+    fn test_to_string_counting_non_finite() {
+        use super::to_string_counting_non_finite;
+
+        #[derive(serde::Serialize)]
+        struct Reading {
+            sensor: &'static str,
+            values: Vec<f64>,
+            mean: f64,
+        }
+
+        let reading = Reading {
+            sensor: "a",
+            values: vec![1.5, f64::NAN, 2.0],
+            mean: f64::NAN,
+        };
+        assert_eq!(
+            to_string_counting_non_finite(&reading).unwrap(),
+            (
+                r#"{"mean":null,"sensor":"a","values":[1.5E0,null,2.0E0]}"#.to_string(),
+                2
+            )
+        );
+        assert_eq!(
+            to_string_counting_non_finite(&json!([1.5, null])).unwrap(),
+            ("[1.5E0,null]".to_string(), 0)
+        );
+        assert_eq!(
+            to_string_counting_non_finite(&(Some(f32::INFINITY), None::<f64>, -f64::INFINITY))
+                .unwrap(),
+            ("[null,null,null]".to_string(), 2)
+        );
+    }
+
+    #[test]
+    fn test_escape_stats() {
+        use super::Canonicalizer;
+
+        let value = json!({ "k\u{0}": "\u{1}\u{2}\u{3}\u{4}\n\t\"\\" });
+        let (serialized, stats) = Canonicalizer::new().to_string_with_stats(&value).unwrap();
+
+        assert_eq!(
+            serialized,
+            r#"{"k\u0000":"\u0001\u0002\u0003\u0004\n\t\"\\"}"#
+        );
+        assert_eq!(stats.escapes, 9);
+        assert_eq!(stats.string_bytes, 10);
+        assert_eq!(stats.escaped_string_bytes, 39);
+        assert!(stats.expansion_ratio() > 3.8);
+
+        let (_, stats) = Canonicalizer::new()
+            .ascii_only(true)
+            .to_string_with_stats(&json!(["plain", "é🦀"]))
+            .unwrap();
+        assert_eq!(stats.escapes, 3);
+        assert_eq!(stats.string_bytes, 11);
+        assert_eq!(stats.escaped_string_bytes, 23);
+        assert_eq!(
+            Canonicalizer::new()
+                .to_string_with_stats(&json!(1))
+                .unwrap()
+                .1
+                .expansion_ratio(),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_with_limits() {
+        use super::{canonicalize_with_limits, CanonicalJSONError};
+
+        let value = json!({ "b": [{ "c": 1, "d": 2 }], "a": null });
+        assert_eq!(
+            canonicalize_with_limits(&value, 2, 3).unwrap(),
+            r#"{"a":null,"b":[{"c":1,"d":2}]}"#
+        );
+        assert!(matches!(
+            canonicalize_with_limits(&value, 2, 2),
+            Err(CanonicalJSONError::DepthLimitExceeded(2))
+        ));
+        assert!(matches!(
+            canonicalize_with_limits(&value, 1, 3),
+            Err(CanonicalJSONError::MemberLimitExceeded(1))
+        ));
+        // scalars have no depth, empty containers count as a level
+        assert!(canonicalize_with_limits(&json!("a"), 0, 0).is_ok());
+        assert!(canonicalize_with_limits(&json!([]), 0, 0).is_err());
+        assert!(canonicalize_with_limits(&json!({}), 0, 1).is_ok());
+    }
+
+    #[test]
+    fn test_canonical_pairs() {
+        use super::canonical_pairs;
+
+        let value = json!({ "z": { "y": 1, "x": 2 }, "\u{fffd}": null, "\u{1f600}": [], "a": "é" });
+        let pairs = canonical_pairs(&value);
+        let keys: Vec<&str> = pairs.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, vec!["a", "z", "\u{1f600}", "\u{fffd}"]);
+        assert_eq!(pairs[1].1, json!({ "x": 2, "y": 1 }));
+        // the same order as the canonical output
+        let rebuilt: String = pairs
+            .iter()
+            .map(|(key, value)| {
+                format!("{}:{}", to_string(key).unwrap(), to_string(value).unwrap())
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        assert_eq!(format!("{{{}}}", rebuilt), to_string(&value).unwrap());
+
+        for other in &[json!(null), json!([{ "a": 1 }]), json!("{}"), json!({})] {
+            assert!(canonical_pairs(other).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_error_precedence() {
+        use super::{CanonicalJSONError, Canonicalizer};
+
+        // the duplicate keys are met first, the depth limit wins
+        let duplicates = Members(vec![
+            ("a".to_string(), json!(1)),
+            ("a".to_string(), json!(2)),
+        ]);
+        let value = (duplicates, vec![vec![vec![1]]]);
+        assert!(matches!(
+            Canonicalizer::new().max_depth(3).to_string(&value),
+            Err(CanonicalJSONError::DepthLimitExceeded(3))
+        ));
+        assert!(matches!(
+            to_string(&value),
+            Err(CanonicalJSONError::DuplicateKey(key)) if key == "a"
+        ));
+        // a malformed document is rejected before anything else
+        assert!(matches!(
+            Canonicalizer::new()
+                .max_depth(1)
+                .canonicalize_slice(b"[[[1]]], 2]"),
+            Err(CanonicalJSONError::JSONError(_))
+        ));
+
+        // too many members wins over the long array and string, met first
+        let value = json!({ "a": [1, 2, 3], "b": "long string", "c": null });
+        let limited = Canonicalizer::new()
+            .max_members(2)
+            .max_array_len(2)
+            .max_string_len(4);
+        assert!(matches!(
+            limited.to_string(&value),
+            Err(CanonicalJSONError::MemberLimitExceeded(2))
+        ));
+        assert!(matches!(
+            limited.clone().max_members(3).to_string(&value),
+            Err(CanonicalJSONError::ArrayLimitExceeded(2))
+        ));
+        assert!(matches!(
+            limited.max_members(3).max_array_len(3).to_string(&value),
+            Err(CanonicalJSONError::SizeLimitExceeded {
+                limit: 4,
+                line: None
+            })
+        ));
+
+        // non-finite numbers come before any limit
+        let strict = Canonicalizer::new()
+            .non_finite(super::NonFinite::Error)
+            .max_depth(1);
+        assert!(matches!(
+            strict.to_string(&(vec![vec![1.0]], f64::NAN)),
+            Err(CanonicalJSONError::NonFiniteNumber(_))
+        ));
+    }
+
+    #[test]
+    fn test_max_array_len() {
+        use super::{CanonicalJSONError, Canonicalizer};
+
+        let limited = Canonicalizer::new().max_array_len(3);
+        let large: Vec<u32> = (0..100_000).collect();
+        assert!(matches!(
+            limited.to_string(&large),
+            Err(CanonicalJSONError::ArrayLimitExceeded(3))
+        ));
+        assert_eq!(
+            limited
+                .to_string(&json!([[1, 2, 3], [], { "a": [4, 5, 6] }]))
+                .unwrap(),
+            r#"[[1,2,3],[],{"a":[4,5,6]}]"#
+        );
+        // inner arrays count apart from the outer ones
+        assert!(matches!(
+            limited.to_string(&json!([1, [2, 3, 4, 5]])),
+            Err(CanonicalJSONError::ArrayLimitExceeded(3))
+        ));
+        assert!(matches!(
+            limited.canonicalize_slice(b"{\"a\":[1,2,3,4]}"),
+            Err(CanonicalJSONError::ArrayLimitExceeded(3))
+        ));
+        assert!(Canonicalizer::new()
+            .max_array_len(0)
+            .to_string(&json!([]))
+            .is_ok());
+    }
+
+    /// Serializes as infinitely nested arrays and objects.
+    struct Recursive(bool);
+
+    impl serde::Serialize for Recursive {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            use serde::ser::{SerializeMap, SerializeSeq};
+            if self.0 {
+                let mut seq = serializer.serialize_seq(Some(1))?;
+                seq.serialize_element(&Recursive(false))?;
+                seq.end()
+            } else {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("next", &Recursive(true))?;
+                map.end()
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_string_len() {
+        use super::{CanonicalJSONError, Canonicalizer};
+
+        let limited = Canonicalizer::new().max_string_len(4);
+        // measured before escaping: "a\"\nb" is 4 bytes long, "été" 5
+        for value in &[json!({ "key": "a\"\nb" }), json!({ "\"\n\\\t": [] })] {
+            assert!(limited.to_string(value).is_ok(), "{}", value);
+        }
+        for value in &[
+            json!({ "key": "value" }),
+            json!({ "long key": "v" }),
+            json!([{ "a": ["b", "été"] }]),
+            json!({ "a\"\nbc": 1 }),
+        ] {
+            assert!(
+                matches!(
+                    limited.to_string(value),
+                    Err(CanonicalJSONError::SizeLimitExceeded {
+                        limit: 4,
+                        line: None
+                    })
+                ),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_depth_limit_stops_recursive_serialize() {
+        use super::{CanonicalJSONError, Canonicalizer};
+        use serde::Serialize;
+
+        let formatter = Canonicalizer::new().max_depth(100).formatter();
+        let mut serializer = serde_json::Serializer::with_formatter(vec![], formatter);
+        let error = Recursive(true).serialize(&mut serializer).unwrap_err();
+
+        assert!(matches!(
+            CanonicalJSONError::from(error),
+            CanonicalJSONError::DepthLimitExceeded(100)
+        ));
+    }
+
+    #[test]
+    fn test_depth_limit_stops_recursive_non_finite_check() {
+        use super::{CanonicalJSONError, Canonicalizer, NonFinite};
+
+        let canonicalizer = Canonicalizer::new()
+            .non_finite(NonFinite::Error)
+            .max_depth(10);
+        assert!(matches!(
+            canonicalizer.to_string(&Recursive(true)),
+            Err(CanonicalJSONError::DepthLimitExceeded(10))
+        ));
+        // non-finite numbers within the limit still come first
+        assert!(matches!(
+            canonicalizer.to_string(&(f64::NAN, Recursive(true))),
+            Err(CanonicalJSONError::NonFiniteNumber(_))
+        ));
+        assert!(matches!(
+            canonicalizer.max_depth(2).to_string(&[[[f64::NAN]]]),
+            Err(CanonicalJSONError::DepthLimitExceeded(2))
+        ));
+    }
+
+    #[test]
+    fn test_canonicalize_all() {
+        let values = vec![
+            json!({ "id": "1", "a": "❤" }),
+            json!(null),
+            json!([{ "b": 2, "a": 1 }, 0.5]),
+        ];
+        let expected: Vec<String> = values.iter().map(|v| to_string(v).unwrap()).collect();
+
+        assert_eq!(canonicalize_all(&values).unwrap(), expected);
+        assert!(canonicalize_all(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_canonical_bytes() {
+        let value = json!({ "id": "1", "b": [1.5, null], "a": "❤" });
+        let bytes: Vec<u8> = canonical_bytes(&value).unwrap().collect();
+
+        assert_eq!(bytes, to_string(&value).unwrap().into_bytes());
+    }
+
+    #[test]
+    fn test_to_vec() {
+        use super::{CanonicalJSONError, Canonicalizer};
+
+        let value = json!({ "id": "1", "b": [1.5, null], "a": "❤" });
+        assert_eq!(
+            to_vec(&value).unwrap(),
+            to_string(&value).unwrap().into_bytes()
+        );
+        assert_eq!(
+            Canonicalizer::new()
+                .ascii_only(true)
+                .to_vec(&value)
+                .unwrap(),
+            br#"{"a":"\u2764","b":[1.5E0,null],"id":"1"}"#
+        );
+        assert!(matches!(
+            Canonicalizer::new().max_depth(1).to_vec(&value),
+            Err(CanonicalJSONError::DepthLimitExceeded(1))
+        ));
+    }
+
+    #[test]
+    fn test_to_writer_io() {
+        use super::{to_writer_io, CanonicalJSONError};
+        use std::io;
+
+        let mut output = vec![];
+        to_writer_io(&mut output, &json!({ "b": [1.5], "a": "é" })).unwrap();
+        assert_eq!(output, "{\"a\":\"é\",\"b\":[1.5E0]}".as_bytes());
+
+        // errors of the writer come back unchanged
+        struct Closed;
+        impl io::Write for Closed {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let error = to_writer_io(Closed, &json!([1])).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(error.to_string(), "closed");
+
+        // other failures are wrapped
+        let duplicate = Members(vec![
+            ("a".to_string(), json!(1)),
+            ("a".to_string(), json!(2)),
+        ]);
+        let error = to_writer_io(vec![], &duplicate).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(matches!(
+            error.into_inner().unwrap().downcast_ref::<CanonicalJSONError>(),
+            Some(CanonicalJSONError::DuplicateKey(key)) if key == "a"
+        ));
+    }
+
+    #[test]
+    fn test_to_string_into() {
+        use super::{to_string_into, CanonicalJSONError};
+        use std::io::Write;
+
+        let mut out = String::new();
+        to_string_into(&mut out, &json!({ "b": "é", "a": [1.5] })).unwrap();
+        out.push('\n');
+        to_string_into(&mut out, &json!("😀")).unwrap();
+        assert_eq!(out, "{\"a\":[1.5E0],\"b\":\"é\"}\n\"😀\"");
+
+        // chunks that are not whole characters are refused
+        let mut out = String::new();
+        let mut writer = super::StringWriter(&mut out);
+        writer.write_all("é".as_bytes()).unwrap();
+        let error = writer.write_all(&"é".as_bytes()[..1]).unwrap_err();
+        assert!(matches!(
+            CanonicalJSONError::from(serde_json::Error::io(error)),
+            CanonicalJSONError::Utf8Error(_)
+        ));
+        assert_eq!(out, "é");
+    }
+
+    #[test]
+    fn test_to_boxed_slice() {
+        use super::to_boxed_slice;
+
+        let value = json!({ "id": "1", "tags": ["a", "b"], "n": [1, 2.5e10] });
+        let bytes = to_boxed_slice(&value).unwrap();
+        let expected = to_vec(&value).unwrap();
+        assert_eq!(&*bytes, &expected[..]);
+        assert_eq!(bytes.len(), expected.len());
+        assert_eq!(bytes.into_vec().capacity(), expected.len());
+    }
+
+    #[test]
+    fn test_leading_zeros() {
+        for input in &["007", "-007", "00", "[01]", "{\"a\":-00}"] {
+            let error = serde_json::from_str::<Value>(input).unwrap_err();
+            assert!(error.is_syntax(), "{} should not parse", input);
+        }
+
+        for &(number, expected) in &[("007", "7"), ("-007", "-7"), ("000", "0"), ("10", "10")] {
+            let mut formatter = CanonicalFormatter::new();
+            let mut bytes = vec![];
+            serde_json::ser::Formatter::write_number_str(&mut formatter, &mut bytes, number)
+                .unwrap();
+            assert_eq!(String::from_utf8(bytes).unwrap(), expected);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_integers_have_no_leading_zeros(signed in any::<i64>(), unsigned in any::<u64>()) {
+            for serialized in &[to_string(&json!(signed)).unwrap(), to_string(&json!(unsigned)).unwrap()] {
+                let digits = serialized.trim_start_matches('-');
+                prop_assert!(digits == "0" || !digits.starts_with('0'), "{}", serialized);
+            }
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_slice() {
+        let inputs = [
+            r#"{"id": "1", "title": "I \u2764 testing", "tags": ["a", "b\n"], "n": [1, -2, 1.5]}"#,
+            r#"{"b": {"d": null, "c": true}, "a": [{"z": 1, "y": 2}]}"#,
+            r#"{"\u00e9": 1, "e": 2, "": 3}"#,
+            r#""\ud83e\udd80""#,
+            "-1.5e300",
+            "[]",
+        ];
+        for input in &inputs {
+            let value: Value = serde_json::from_str(input).unwrap();
+            assert_eq!(
+                canonicalize_slice(input.as_bytes()).unwrap(),
+                to_string(&value).unwrap(),
+                "{}",
+                input
+            );
+        }
+
+        assert!(canonicalize_slice(b"{} x").is_err());
+        assert!(canonicalize_slice(b"{\"a\":").is_err());
+    }
+
+    #[test]
+    fn test_canonicalize_slice_duplicate_keys() {
+        use super::{CanonicalJSONError, Canonicalizer, DuplicateKeyPolicy};
+
+        let input = br#"{"a": 1, "b": {"c": 2, "c": 3}, "a": 4}"#;
+        assert!(matches!(
+            canonicalize_slice(input),
+            Err(CanonicalJSONError::DuplicateKey(key)) if key == "c"
+        ));
+        assert!(matches!(
+            super::canonicalize(r#"{"a":1,"a":1}"#),
+            Err(CanonicalJSONError::DuplicateKey(key)) if key == "a"
+        ));
+        assert!(matches!(
+            super::is_canonical(r#"{"a":1,"a":1}"#),
+            Err(CanonicalJSONError::DuplicateKey(_))
+        ));
+        let policy = |policy| {
+            Canonicalizer::new()
+                .duplicate_key_policy(policy)
+                .canonicalize_slice(input)
+                .unwrap()
+        };
+        assert_eq!(
+            policy(DuplicateKeyPolicy::FirstWins),
+            r#"{"a":1,"b":{"c":2}}"#
+        );
+        // what parsing into a `serde_json::Value` gives
+        let value: Value = serde_json::from_slice(input).unwrap();
+        assert_eq!(
+            policy(DuplicateKeyPolicy::LastWins),
+            to_string(&value).unwrap()
+        );
+        assert_eq!(
+            policy(DuplicateKeyPolicy::LastWins),
+            r#"{"a":4,"b":{"c":3}}"#
+        );
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let input = r#"{ "id": "1", "sig": null, "body": { "b": [1, 1e2], "a": "\u2764" } }"#;
+        assert_eq!(
+            super::canonicalize(input).unwrap(),
+            r#"{"body":{"a":"❤","b":[1,1.0E2]},"id":"1","sig":null}"#
+        );
+        match super::canonicalize("{\"a\" 1}") {
+            Err(super::CanonicalJSONError::JSONError(error)) => assert!(error.is_syntax()),
+            other => panic!("expected a syntax error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_malformed() {
+        // inputs of the kinds found in the `malformed` directory of the spec's
+        // test suite, with the error `serde_json` rejects them with
+        let cases = [
+            ("0x1A", "trailing characters"),
+            ("+1", "expected value"),
+            ("01", "invalid number"),
+            ("--1", "invalid number"),
+            (".5", "expected value"),
+            ("1.", "EOF while parsing a value"),
+            ("1e+", "EOF while parsing a value"),
+            ("-Infinity", "invalid number"),
+            ("NaN", "expected value"),
+            ("undefined", "expected value"),
+            ("tru", "EOF while parsing a value"),
+            ("'a'", "expected value"),
+            ("\"abc", "EOF while parsing a string"),
+            (
+                "\"a\tb\"",
+                "control character (\\u0000-\\u001F) found while parsing a string",
+            ),
+            ("\"\\x41\"", "invalid escape"),
+            ("\"\\u12\"", "EOF while parsing a string"),
+            ("[1, 2", "EOF while parsing a list"),
+            ("[1 2]", "expected `,` or `]`"),
+            ("[1,]", "trailing comma"),
+            ("{\"a\": 1", "EOF while parsing an object"),
+            ("{\"a\" 1}", "expected `:`"),
+            ("{\"a\":1,}", "trailing comma"),
+            ("{a: 1}", "key must be a string"),
+            ("{1: 2}", "key must be a string"),
+            ("}", "expected value"),
+            ("[1] // comment", "trailing characters"),
+            ("1 2", "trailing characters"),
+            ("\u{feff}1", "expected value"),
+            ("", "EOF while parsing a value"),
+        ];
+        for (input, message) in &cases {
+            match super::canonicalize(input) {
+                Err(super::CanonicalJSONError::JSONError(error)) => assert!(
+                    error.to_string().starts_with(message),
+                    "{:?}: {}",
+                    input,
+                    error
+                ),
+                other => panic!("{:?} should be rejected, got {:?}", input, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_with_value_transform() {
+        use super::canonicalize_with_value_transform;
+
+        let lowercase = |value: &mut Value| {
+            if let Value::String(string) = value {
+                *string = string.to_lowercase();
+            }
+        };
+        let value = json!({
+            "Name": "Ada LOVELACE",
+            "tags": ["A", { "B": "Ünïcode" }],
+            "n": 1.5,
+        });
+        assert_eq!(
+            canonicalize_with_value_transform(&value, &lowercase).unwrap(),
+            r#"{"Name":"ada lovelace","n":1.5E0,"tags":["a",{"B":"ünïcode"}]}"#
+        );
+
+        // containers come after their contents
+        let count = |value: &mut Value| {
+            let nested = match value {
+                Value::Array(values) => values.iter().filter_map(Value::as_u64).sum(),
+                _ => 1,
+            };
+            *value = json!(nested);
+        };
+        assert_eq!(
+            canonicalize_with_value_transform(&json!([[null, null], "a", []]), &count).unwrap(),
+            "3"
+        );
+    }
+
+    #[test]
+    fn test_canonical_mismatch() {
+        use super::{canonical_mismatch, is_canonical};
+
+        for input in &[r#"{"a":1,"b":[true,null]}"#, r#""é""#, "1.5E0", "[]"] {
+            assert_eq!(canonical_mismatch(input).unwrap(), None, "{}", input);
+            assert!(is_canonical(input).unwrap());
+        }
+        for (input, offset) in &[
+            // unsorted keys
+            (r#"{"b":1,"a":2}"#, 2),
+            (r#"{"a":{"d":1,"c":2}}"#, 7),
+            // whitespace
+            (r#"{"a": 1}"#, 5),
+            (" []", 0),
+            ("[]\n", 2),
+            // numbers and escapes
+            ("[1,1.5]", 6),
+            (r#""\u00e9""#, 1),
+        ] {
+            assert_eq!(
+                canonical_mismatch(input).unwrap(),
+                Some(*offset),
+                "{}",
+                input
+            );
+            assert!(!is_canonical(input).unwrap());
+        }
+        assert!(canonical_mismatch("{\"a\":").is_err());
+    }
+
+    #[test]
+    fn test_canonicalize_slice_lossy() {
+        use super::canonicalize_slice_lossy;
+
+        let input = b"{\"z\": \"ok\", \"a\xff\": [\"\xc3\", \"\xf0\x9f\x98\"]}";
+        assert_eq!(
+            canonicalize_slice_lossy(input).unwrap(),
+            "{\"a\u{fffd}\":[\"\u{fffd}\",\"\u{fffd}\"],\"z\":\"ok\"}".as_bytes()
+        );
+        assert!(canonicalize_slice(input).is_err());
+
+        // valid input is left alone
+        let input = r#"{"b": "\u00e9", "a": "é"}"#;
+        assert_eq!(
+            canonicalize_slice_lossy(input.as_bytes()).unwrap(),
+            canonicalize_slice(input.as_bytes()).unwrap().as_bytes()
+        );
+        // outside of strings, a replacement character is still invalid JSON
+        assert!(canonicalize_slice_lossy(b"[1,\xff2]").is_err());
+    }
 
-        test_canonical_json!(
-            {
-                "def": "bar",
-                "abc": json!(0.000000930258908),
-                "ghi": json!(1000000000000000000000.0_f64),
-                "rust": "❤",
-                "zoo": [
-                    "zorilla",
-                    "anteater"
-                ]
-            },
-            r#"{"abc":9.30258908E-7,"def":"bar","ghi":1E21,"rust":"\u2764","zoo":["zorilla","anteater"]}"#
+    #[test]
+    fn test_parse_and_canonicalize() {
+        let input = r#"{"id": "1", "title": "I \u2764 testing", "n": [1, 1.5, -0.0], "a": {}}"#;
+        let (value, canonical) = parse_and_canonicalize(input).unwrap();
+        assert_eq!(value, serde_json::from_str::<Value>(input).unwrap());
+        assert_eq!(canonical, to_string(&value).unwrap());
+
+        assert!(parse_and_canonicalize("{} x").is_err());
+    }
+
+    #[test]
+    fn test_canonicalize_slice_recursion_limit() {
+        for input in &["[".repeat(10000), "{\"a\":".repeat(10000), "[".repeat(128)] {
+            match canonicalize_slice(input.as_bytes()) {
+                Err(super::CanonicalJSONError::JSONError(error)) => {
+                    assert!(
+                        error.to_string().contains("recursion limit exceeded"),
+                        "{}",
+                        error
+                    )
+                }
+                other => panic!("expected a recursion error, got {:?}", other),
+            }
+        }
+
+        let nested = format!("{}{}", "[".repeat(127), "]".repeat(127));
+        assert_eq!(canonicalize_slice(nested.as_bytes()).unwrap(), nested);
+    }
+
+    #[test]
+    fn test_to_fixed_buf() {
+        let value = json!({ "id": "1", "a": [1.5, null] });
+        let expected = to_string(&value).unwrap();
+
+        let mut buf = [0xff; 64];
+        let len = to_fixed_buf(&value, &mut buf).unwrap();
+        assert_eq!(&buf[..len], expected.as_bytes());
+
+        let mut exact = vec![0; expected.len()];
+        assert_eq!(to_fixed_buf(&value, &mut exact).unwrap(), expected.len());
+    }
+
+    #[test]
+    fn test_to_writer() {
+        let value = json!({ "id": "1", "title": "I ❤ testing", "a": [1.5, null, {}] });
+        let mut output = vec![];
+        to_writer(&mut output, &value).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            to_string(&value).unwrap()
         );
 
-        // serialize empty array
-        test_canonical_json!([], "[]");
+        let error = to_writer(BrokenPipe { remaining: 3 }, &value).unwrap_err();
+        assert!(matches!(error, super::CanonicalJSONError::Io(_)));
+        assert_eq!(error.io_kind(), Some(std::io::ErrorKind::BrokenPipe));
+    }
 
-        // serialize array should preserve array order
-        test_canonical_json!((vec!["one", "two", "three"]), r#"["one","two","three"]"#);
+    #[test]
+    fn test_sink_and_canonical_len() {
+        let values = [
+            json!({ "id": "1", "title": "I ❤ testing 🦀", "a": [1.5, null, "\n"] }),
+            json!(null),
+            json!({}),
+            json!("\u{7f}\"\\"),
+        ];
+        for value in &values {
+            to_writer(std::io::sink(), value).unwrap();
+            assert_eq!(
+                canonical_len(value).unwrap(),
+                to_string(value).unwrap().len()
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_writer_tee() {
+        let value = json!({ "id": "1", "title": "I ❤ testing", "a": [1.5, null] });
+        let (mut first, mut second) = (vec![], vec![]);
+        to_writer_tee(&mut first, &mut second, &value).unwrap();
+
+        let expected = to_string(&value).unwrap().into_bytes();
+        assert_eq!(first, expected);
+        assert_eq!(second, expected);
+    }
 
-        test_canonical_json!((vec![json!({ "key": "✓" })]), r#"[{"key":"\u2713"}]"#);
+    #[test]
+    fn test_to_writer_tee_error() {
+        let mut first = vec![];
+        let error = to_writer_tee(
+            &mut first,
+            BrokenPipe { remaining: 4 },
+            &json!({ "id": "1" }),
+        )
+        .unwrap_err();
+        assert_eq!(error.io_kind(), Some(std::io::ErrorKind::BrokenPipe));
+    }
 
-        // escapes unicode values with 1 preceding zeros
-        test_canonical_json!((vec![json!({ "key": "ę" })]), r#"[{"key":"\u0119"}]"#);
+    #[test]
+    fn test_to_fixed_buf_overflow() {
+        let value = json!({ "id": "1", "a": [1.5, null] });
+        let mut buf = [0; 8];
+        match to_fixed_buf(&value, &mut buf) {
+            Err(super::CanonicalJSONError::BufferOverflow(capacity)) => assert_eq!(capacity, 8),
+            other => panic!("expected a buffer overflow, got {:?}", other),
+        }
+    }
 
-        // escapes unicode values with 2 preceding zeros
-        test_canonical_json!((vec![json!({ "key": "é" })]), r#"[{"key":"\u00e9"}]"#);
+    fn to_string_generic<T: serde::Serialize>(input: &T) -> String {
+        to_string(input).unwrap()
+    }
 
-        // serialize array preserves data
-        test_canonical_json!(
-            (vec![
-                json!({ "foo": "bar", "last_modified": "12345", "id": "1" }),
-                json!({ "bar": "baz", "last_modified": "45678", "id": "2" }),
-            ]),
-            r#"[{"foo":"bar","id":"1","last_modified":"12345"},{"bar":"baz","id":"2","last_modified":"45678"}]"#
+    /// Object members serialized in the given order.
+    struct Members(Vec<(String, Value)>);
+
+    impl serde::Serialize for Members {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            use serde::ser::SerializeMap;
+            let mut map = serializer.serialize_map(Some(self.0.len()))?;
+            for (key, value) in &self.0 {
+                map.serialize_entry(key, value)?;
+            }
+            map.end()
+        }
+    }
+
+    #[test]
+    fn test_members_are_sorted_whatever_their_order() {
+        let sorted = Members(vec![
+            ("".to_string(), json!(null)),
+            (
+                "a".to_string(),
+                json!({ "y": 1, "x": [{ "d": 1, "c": 2 }] }),
+            ),
+            ("b\n".to_string(), json!("❤")),
+            ("id".to_string(), json!(1.5)),
+        ]);
+        let mut shuffled = Members(sorted.0.clone());
+        shuffled.0.reverse();
+        let expected = r#"{"":null,"a":{"x":[{"c":2,"d":1}],"y":1},"b\n":"❤","id":1.5E0}"#;
+
+        assert_eq!(to_string_generic(&sorted), expected);
+        assert_eq!(to_string_generic(&shuffled), expected);
+        let value = Value::Object(sorted.0.into_iter().collect());
+        assert_eq!(to_string(&value).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_keys_sort_by_utf16_code_units() {
+        use super::Canonicalizer;
+
+        // U+FFFD comes before U+1F600 in UTF-8, but after its surrogates in UTF-16
+        test_canonical_json!({"\u{fffd}": 1, "\u{1f600}": 2}, "{\"\u{1f600}\":2,\"\u{fffd}\":1}");
+        test_canonical_json!({"\u{e000}": 1, "\u{d7ff}": 2, "\u{10000}": 3}, "{\"\u{d7ff}\":2,\"\u{10000}\":3,\"\u{e000}\":1}");
+
+        // members already in byte order are still reordered
+        let members = Members(vec![
+            ("\u{fffd}".to_string(), json!(1)),
+            ("\u{1f600}".to_string(), json!(2)),
+        ]);
+        assert_eq!(
+            Canonicalizer::new()
+                .ascii_only(true)
+                .to_string(&members)
+                .unwrap(),
+            r#"{"\ud83d\ude00":2,"\ufffd":1}"#
         );
+    }
 
-        // serialize does not add space separators
-        test_canonical_json!(
-            (vec![
-                json!({ "foo": "bar", "last_modified": "12345", "id": "1" }),
-                json!({ "bar": "baz", "last_modified": "45678", "id": "2" }),
+    #[test]
+    fn test_control_characters_in_keys() {
+        let value = json!({ "line1\nline2": "line1\nline2", "a\tb": "a\tb", "\u{0}": "\u{0}" });
+        // sorted by the raw keys (NUL, tab, newline), not by their escapes
+        assert_eq!(
+            to_string(&value).unwrap(),
+            r#"{"\u0000":"\u0000","a\tb":"a\tb","line1\nline2":"line1\nline2"}"#
+        );
+        test_canonical_json!({"\n": 1, "\t": 2, "\u{0}": 3, "\u{1f}": 4, "\\": 5}, r#"{"\u0000":3,"\t":2,"\n":1,"\u001f":4,"\\":5}"#);
+    }
+
+    #[test]
+    fn test_numeric_keys_sort_as_strings() {
+        test_canonical_json!({"10": 0, "2": 0, "1": 0}, r#"{"1":0,"10":0,"2":0}"#);
+        test_canonical_json!({"-1": 0, "1e3": 0, "1.5": 0, "100": 0}, r#"{"-1":0,"1.5":0,"100":0,"1e3":0}"#);
+
+        // integer keys of a map come in numeric order, and are written as strings
+        let map: std::collections::BTreeMap<u32, u32> =
+            vec![(10, 0), (2, 0), (1, 0)].into_iter().collect();
+        assert_eq!(to_string_generic(&map), r#"{"1":0,"10":0,"2":0}"#);
+        let map: std::collections::BTreeMap<i64, bool> = vec![(-5, true), (-10, false), (3, true)]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            to_string_generic(&map),
+            r#"{"-10":false,"-5":true,"3":true}"#
+        );
+    }
+
+    #[test]
+    fn test_duplicate_key_policy() {
+        use super::{CanonicalJSONError, Canonicalizer, DuplicateKeyPolicy};
+        use serde::Serialize;
+
+        let members = Members(vec![
+            ("b".to_string(), json!(1)),
+            ("a".to_string(), json!({ "x": 1, "x": 2 })),
+            ("b".to_string(), json!(2)),
+            ("c".to_string(), json!(3)),
+            ("b".to_string(), json!(4)),
+        ]);
+        let serialize = |policy| {
+            let formatter = Canonicalizer::new()
+                .duplicate_key_policy(policy)
+                .formatter();
+            let mut serializer = serde_json::Serializer::with_formatter(vec![], formatter);
+            members
+                .serialize(&mut serializer)
+                .map(|()| String::from_utf8(serializer.into_inner()).unwrap())
+                .map_err(CanonicalJSONError::from)
+        };
+
+        match serialize(DuplicateKeyPolicy::Error) {
+            Err(CanonicalJSONError::DuplicateKey(key)) => assert_eq!(key, "b"),
+            other => panic!("expected a duplicate key error, got {:?}", other),
+        }
+        assert_eq!(
+            serialize(DuplicateKeyPolicy::FirstWins).unwrap(),
+            r#"{"a":{"x":2},"b":1,"c":3}"#
+        );
+        assert_eq!(
+            serialize(DuplicateKeyPolicy::LastWins).unwrap(),
+            r#"{"a":{"x":2},"b":4,"c":3}"#
+        );
+
+        // keys written with escapes are detected too
+        let escaped = Members(vec![
+            ("é".to_string(), json!(1)),
+            ("\u{e9}".to_string(), json!(2)),
+        ]);
+        let mut serializer =
+            serde_json::Serializer::with_formatter(vec![], CanonicalFormatter::new());
+        assert!(escaped.serialize(&mut serializer).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_keys_per_object() {
+        use super::CanonicalJSONError;
+
+        #[derive(serde::Serialize)]
+        struct Outer {
+            id: u32,
+            inner: Members,
+        }
+
+        // the same key at another level is not a duplicate
+        let distinct = Outer {
+            id: 1,
+            inner: Members(vec![("id".to_string(), json!(2))]),
+        };
+        assert_eq!(
+            to_string(&distinct).unwrap(),
+            r#"{"id":1,"inner":{"id":2}}"#
+        );
+
+        let nested = Outer {
+            id: 1,
+            inner: Members(vec![
+                ("id".to_string(), json!(2)),
+                ("key".to_string(), json!(null)),
+                ("id".to_string(), json!(3)),
             ]),
-            r#"[{"foo":"bar","id":"1","last_modified":"12345"},{"bar":"baz","id":"2","last_modified":"45678"}]"#
+        };
+        assert!(matches!(
+            to_string(&nested),
+            Err(CanonicalJSONError::DuplicateKey(key)) if key == "id"
+        ));
+    }
+
+    #[test]
+    fn test_wide_object() {
+        let members: Vec<(String, Value)> = (0..2000)
+            .map(|i| (format!("{:x}", i * 7919), json!([i])))
+            .collect();
+        let mut reversed = Members(members.clone());
+        reversed.0.sort_by(|a, b| b.0.cmp(&a.0));
+        let value = Value::Object(members.into_iter().collect());
+
+        assert_eq!(to_string_generic(&reversed), to_string(&value).unwrap());
+    }
+
+    #[test]
+    fn test_std_types() {
+        use std::net::Ipv4Addr;
+        use std::path::PathBuf;
+        use std::time::Duration;
+
+        assert_eq!(
+            to_string_generic(&Duration::new(5, 30)),
+            r#"{"nanos":30,"secs":5}"#
+        );
+        assert_eq!(
+            to_string_generic(&Ipv4Addr::new(127, 0, 0, 1)),
+            r#""127.0.0.1""#
+        );
+        assert_eq!(
+            to_string_generic(&PathBuf::from("/tmp/été\\\"x\"")),
+            r#""/tmp/été\\\"x\"""#
+        );
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml_keys() {
+        // YAML keys of any type become strings, and sort as strings
+        let value: Value = serde_yaml::from_str("{2: a, 10: b, true: c}").unwrap();
+        assert_eq!(
+            to_string(&value).unwrap(),
+            r#"{"10":"b","2":"a","true":"c"}"#
+        );
+
+        let value: Value = serde_yaml::from_str("{b: 1, 1.5: 2, null: 3, -1: 4}").unwrap();
+        assert_eq!(
+            to_string(&value).unwrap(),
+            r#"{"-1":4,"1.5":2,"b":1,"null":3}"#
+        );
+    }
+
+    #[test]
+    fn test_untagged_enum() {
+        #[derive(serde::Serialize)]
+        #[serde(untagged)]
+        enum Event {
+            Scalar(u32),
+            List(Vec<Event>),
+            Record {
+                title: String,
+                id: u32,
+                attachment: Option<Box<Event>>,
+            },
+        }
+
+        let event = Event::List(vec![
+            Event::Scalar(1),
+            Event::Record {
+                title: "I ❤ testing".to_string(),
+                id: 2,
+                attachment: Some(Box::new(Event::Record {
+                    title: "nested".to_string(),
+                    id: 3,
+                    attachment: None,
+                })),
+            },
+        ]);
+        assert_eq!(
+            to_string_generic(&event),
+            r#"[1,{"attachment":{"attachment":null,"id":3,"title":"nested"},"id":2,"title":"I ❤ testing"}]"#
+        );
+    }
+
+    #[test]
+    fn test_cow_keys() {
+        use std::borrow::Cow;
+        use std::collections::{BTreeMap, HashMap};
+
+        let mut tree: BTreeMap<Cow<str>, Value> = BTreeMap::new();
+        tree.insert(Cow::Borrowed("été"), json!(1));
+        tree.insert(Cow::Owned(String::from("b\"q")), json!(2));
+        tree.insert(Cow::Borrowed("a"), json!({ "z": null, "y": [] }));
+        let expected = r#"{"a":{"y":[],"z":null},"b\"q":2,"été":1}"#;
+        assert_eq!(to_string_generic(&tree), expected);
+
+        let hashed: HashMap<Cow<str>, Value> = tree.into_iter().collect();
+        assert_eq!(to_string_generic(&hashed), expected);
+    }
+
+    #[test]
+    fn test_options() {
+        assert_eq!(to_string_generic(&Some(42)), "42");
+        assert_eq!(to_string_generic(&None::<i32>), "null");
+        assert_eq!(to_string_generic(&Some(Some(42))), "42");
+        assert_eq!(to_string_generic(&Some(None::<i32>)), "null");
+        assert_eq!(
+            to_string_generic(&Some(json!({"b": 1, "a": [null]}))),
+            r#"{"a":[null],"b":1}"#
+        );
+        assert_eq!(to_string(&json!(Some(Some(1.5)))).unwrap(), "1.5E0");
+        assert_eq!(to_string(&json!(None::<i32>)).unwrap(), "null");
+    }
+
+    #[test]
+    fn test_to_string_shared_values() {
+        let value = json!({ "b": "b", "a": ["❤"] });
+        let expected = r#"{"a":["❤"],"b":"b"}"#;
+
+        assert_eq!(to_string(&Arc::new(value.clone())).unwrap(), expected);
+        assert_eq!(to_string(&Rc::new(value)).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_to_string_derived_struct() {
+        #[derive(serde::Serialize)]
+        struct Record {
+            title: &'static str,
+            id: u32,
+            #[serde(flatten)]
+            extra: Value,
+        }
+
+        let record = Record {
+            title: "I ❤ testing",
+            id: 1,
+            extra: json!({ "b": [2.5], "a": null }),
+        };
+        assert_eq!(
+            to_string(&record).unwrap(),
+            r#"{"a":null,"b":[2.5E0],"id":1,"title":"I ❤ testing"}"#
+        );
+
+        let clash = Record {
+            title: "a",
+            id: 1,
+            extra: json!({ "id": 2 }),
+        };
+        assert!(matches!(
+            to_string(&clash),
+            Err(super::CanonicalJSONError::DuplicateKey(key)) if key == "id"
+        ));
+    }
+
+    #[test]
+    fn test_normalize_number() {
+        use super::normalize_number;
+
+        for (input, expected) in &[
+            ("1e0", "1.0E0"),
+            ("1.5e1", "1.5E1"),
+            ("-2.25e-7", "-2.25E-7"),
+            ("1e+0", "1.0E0"),
+            ("3.0e+05", "3.0E5"),
+            ("4E+010", "4.0E10"),
+            ("5E-0012", "5.0E-12"),
+            ("6.1E0", "6.1E0"),
+            ("0e0", "0.0E0"),
+            ("42", "42"),
+        ] {
+            assert_eq!(normalize_number(input.to_string()), *expected, "{}", input);
+        }
+        for value in &[0.1, -1e100, 123456.789, f64::MAX, f64::MIN_POSITIVE] {
+            assert_eq!(
+                normalize_number(format!("{:e}", value)),
+                to_string(value).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_sets_are_arrays() {
+        use std::collections::{BTreeSet, HashSet};
+
+        let words = ["pear", "apple", "fig", "Banana", "é"];
+        let sorted: BTreeSet<&str> = words.iter().cloned().collect();
+        assert_eq!(
+            to_string(&sorted).unwrap(),
+            r#"["Banana","apple","fig","pear","é"]"#
+        );
+
+        // array order is kept as it is, hash order included
+        let hashed: HashSet<&str> = words.iter().cloned().collect();
+        let iteration_order: Vec<&str> = hashed.iter().cloned().collect();
+        assert_eq!(
+            to_string(&hashed).unwrap(),
+            to_string(&iteration_order).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_from_str() {
+        use super::{from_str, CanonicalJSONError};
+
+        let canonical = r#"{"a":[1,1.5E0,null],"b":{"":"❤ \"\u001f"}}"#;
+        let value = from_str(canonical).unwrap();
+        assert_eq!(value["b"], json!({ "": "❤ \"\u{1f}" }));
+        assert_eq!(to_string(&value).unwrap(), canonical);
+
+        for input in &[
+            "{\"a\":1}\n",
+            " {\"a\":1}",
+            "{\"a\": 1}",
+            r#"{"a":"\u2764"}"#,
+            r#"{"a":"\u001F"}"#,
+            r#"{"a":"\/"}"#,
+            r#"{"b":1,"a":2}"#,
+            r#"{"a":1,"a":1}"#,
+            "1.5",
+            "1e2",
+        ] {
+            match from_str(input) {
+                Err(CanonicalJSONError::NotCanonical { expected, found }) => {
+                    assert_eq!(found, *input);
+                    // the canonical form is accepted
+                    assert!(from_str(&expected).is_ok(), "{}", expected);
+                }
+                other => panic!("{:?} for {:?}", other, input),
+            }
+        }
+        assert!(matches!(
+            from_str("{"),
+            Err(CanonicalJSONError::JSONError(_))
+        ));
+    }
+
+    #[test]
+    fn test_to_chunks() {
+        use super::to_chunks;
+
+        let value = json!({
+            "records": (0..20).map(|i| json!({ "id": i, "title": "I ❤ testing" })).collect::<Vec<_>>(),
+            "b": [1.5, null, "\u{1}"],
+        });
+        let expected = to_vec(&value).unwrap();
+        for &chunk_size in &[1, 2, 7, 64, expected.len(), expected.len() + 1] {
+            let mut chunks: Vec<Vec<u8>> = vec![];
+            to_chunks(&value, chunk_size, |chunk| chunks.push(chunk.to_vec())).unwrap();
+            let (last, full) = chunks.split_last().unwrap();
+            assert!(full.iter().all(|chunk| chunk.len() == chunk_size));
+            assert!(!last.is_empty() && last.len() <= chunk_size);
+            assert_eq!(chunks.concat(), expected, "{}", chunk_size);
+        }
+
+        let mut calls = 0;
+        to_chunks(&json!(null), 4, |chunk| {
+            assert_eq!(chunk, b"null");
+            calls += 1;
+        })
+        .unwrap();
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_emit_bom() {
+        use super::Canonicalizer;
+
+        let value = json!({ "b": "\u{feff}", "a": 1 });
+        let mut with_bom = vec![];
+        Canonicalizer::new()
+            .emit_bom(true)
+            .to_writer(&mut with_bom, &value)
+            .unwrap();
+        let mut without_bom = vec![];
+        Canonicalizer::new()
+            .to_writer(&mut without_bom, &value)
+            .unwrap();
+        assert_eq!(without_bom, to_vec(&value).unwrap());
+        assert_eq!(with_bom[..3], [0xEF, 0xBB, 0xBF]);
+        assert_eq!(with_bom[3..], without_bom[..]);
+
+        // only written by `to_writer`
+        let canonicalizer = Canonicalizer::new().emit_bom(true);
+        assert_eq!(canonicalizer.to_vec(&value).unwrap(), without_bom);
+        assert_eq!(
+            canonicalizer.to_string(&value).unwrap().as_bytes(),
+            &without_bom[..]
+        );
+    }
+
+    #[test]
+    fn test_f32() {
+        use super::{is_canonical, Canonicalizer, NumberFormat};
+
+        for &(value, expected) in &[
+            (1.5_f32, "1.5E0"),
+            (0.1, "1.0E-1"),
+            (1e30, "1.0E30"),
+            (-2.5e-3, "-2.5E-3"),
+            (f32::MAX, "3.4028235E38"),
+            (1e-45, "1.0E-45"),
+            (16_777_216.0, "1.6777216E7"),
+        ] {
+            assert_eq!(to_string(&value).unwrap(), expected, "{}", value);
+        }
+        assert!(is_canonical(&to_string(&[1.5_f32, 0.1, -2.5e-3]).unwrap()).unwrap());
+
+        // options apply as they do to f64
+        assert_eq!(
+            Canonicalizer::new()
+                .max_significant_digits(2)
+                .to_string(&1.2345_f32)
+                .unwrap(),
+            "1.2E0"
+        );
+        assert_eq!(
+            Canonicalizer::new()
+                .number_format(NumberFormat::Legacy)
+                .to_string(&[1e30_f32, 0.1])
+                .unwrap(),
+            "[1e+30,0.1]"
+        );
+        assert_eq!(
+            Canonicalizer::new()
+                .plain_decimals(true)
+                .to_string(&1.5_f32)
+                .unwrap(),
+            "1.5"
+        );
+        assert_eq!(to_string(&[f32::NAN]).unwrap(), "[null]");
+    }
+
+    #[test]
+    fn test_negative_zero() {
+        use super::{is_canonical, Canonicalizer, NumberFormat};
+
+        let value = json!({ "a": [-0.0, 0.0], "b": -0.0 });
+        assert_eq!(
+            to_string(&value).unwrap(),
+            r#"{"a":[0.0E0,0.0E0],"b":0.0E0}"#
+        );
+        assert_eq!(to_string(&-0.0_f32).unwrap(), "0.0E0");
+        let parsed: Value = serde_json::from_str("[-0.0, -0e5]").unwrap();
+        assert_eq!(to_string(&parsed).unwrap(), "[0.0E0,0.0E0]");
+        // kept as an integer with `arbitrary_precision`
+        #[cfg(feature = "arbitrary_precision")]
+        assert_eq!(super::canonicalize("-0").unwrap(), "0");
+        assert_eq!(
+            Canonicalizer::new()
+                .number_format(NumberFormat::Legacy)
+                .to_string(&value)
+                .unwrap(),
+            r#"{"a":[0,0],"b":0}"#
+        );
+        assert!(!is_canonical("-0.0E0").unwrap());
+    }
+
+    #[test]
+    fn test_small_integers() {
+        #[derive(serde::Serialize)]
+        struct Small {
+            u8: u8,
+            u16: u16,
+            u32: u32,
+            i8: i8,
+            i16: i16,
+            i32: i32,
+        }
+
+        let max = Small {
+            u8: u8::MAX,
+            u16: u16::MAX,
+            u32: u32::MAX,
+            i8: i8::MAX,
+            i16: i16::MAX,
+            i32: i32::MAX,
+        };
+        assert_eq!(
+            to_string(&max).unwrap(),
+            r#"{"i16":32767,"i32":2147483647,"i8":127,"u16":65535,"u32":4294967295,"u8":255}"#
+        );
+        let min = Small {
+            u8: 0,
+            u16: 0,
+            u32: 0,
+            i8: i8::MIN,
+            i16: i16::MIN,
+            i32: i32::MIN,
+        };
+        assert_eq!(
+            to_string(&min).unwrap(),
+            r#"{"i16":-32768,"i32":-2147483648,"i8":-128,"u16":0,"u32":0,"u8":0}"#
+        );
+        assert_eq!(to_string(&(-1i8, 10u16, -100i32)).unwrap(), "[-1,10,-100]");
+    }
+
+    fn control_heavy_string() -> impl Strategy<Value = String> {
+        prop_oneof!["[\\x00-\\x1f\\x7f\"\\\\a-z ❤𝄞]{0,24}", any::<String>(),]
+    }
+
+    fn arbitrary_value() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            any::<f64>().prop_map(Value::from),
+            control_heavy_string().prop_map(Value::from),
+        ];
+        leaf.prop_recursive(4, 64, 8, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..8).prop_map(Value::from),
+                prop::collection::btree_map(control_heavy_string(), inner, 0..8)
+                    .prop_map(|members| Value::Object(members.into_iter().collect())),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_output_has_no_unescaped_control_bytes(value in arbitrary_value()) {
+            let serialized = to_string(&value).unwrap();
+            prop_assert!(serialized.bytes().all(|byte| byte >= 0x20), "{:?}", serialized);
+        }
+
+        #[test]
+        fn test_strings_round_trip(string in any::<String>()) {
+            let value = json!({ string.clone(): [string.clone()] });
+            let serialized = to_string(&value).unwrap();
+            prop_assert_eq!(serde_json::from_str::<Value>(&serialized).unwrap(), value.clone());
+
+            let ascii = super::Canonicalizer::new().ascii_only(true).to_string(&value).unwrap();
+            prop_assert!(ascii.is_ascii());
+            prop_assert_eq!(serde_json::from_str::<Value>(&ascii).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_non_finite_policy_for_f64() {
+        use super::{CanonicalJSONError, Canonicalizer, NonFinite};
+        use serde_json::ser::Formatter;
+
+        let mut output = vec![];
+        let mut formatter = Canonicalizer::new().formatter();
+        formatter.write_f64(&mut output, f64::NAN).unwrap();
+        assert_eq!(output, b"null");
+
+        let mut formatter = Canonicalizer::new()
+            .non_finite(NonFinite::Error)
+            .formatter();
+        let error = formatter.write_f64(&mut vec![], f64::INFINITY).unwrap_err();
+        let error = serde_json::Error::io(error);
+        assert!(matches!(
+            CanonicalJSONError::from(error),
+            CanonicalJSONError::NonFiniteNumber(repr) if repr == "inf"
+        ));
+    }
+
+    #[test]
+    fn test_non_finite_policy_through_serialize() {
+        use super::{CanonicalJSONError, Canonicalizer, NonFinite};
+        use std::collections::BTreeMap;
+
+        fn rejected<T: std::fmt::Debug>(result: Result<T, CanonicalJSONError>) -> String {
+            match result {
+                Err(CanonicalJSONError::NonFiniteNumber(repr)) => repr,
+                other => panic!("expected a non-finite error, got {:?}", other),
+            }
+        }
+
+        let strict = Canonicalizer::new().non_finite(NonFinite::Error);
+        assert_eq!(rejected(strict.to_string(&[1.5, f64::NAN])), "NaN");
+        assert_eq!(rejected(strict.to_vec(&Some(f32::INFINITY))), "inf");
+        let map: BTreeMap<&str, f64> = vec![("b", -f64::INFINITY), ("a", f64::NAN)]
+            .into_iter()
+            .collect();
+        assert_eq!(rejected(strict.to_writer(vec![], &map)), "NaN");
+        assert_eq!(
+            strict.to_string(&(1.5, -2.0, json!(null))).unwrap(),
+            "[1.5E0,-2.0E0,null]"
+        );
+
+        // the default policy still writes them as null
+        assert_eq!(to_string(&[1.5, f64::NAN]).unwrap(), "[1.5E0,null]");
+    }
+
+    #[cfg(feature = "arbitrary_precision")]
+    #[test]
+    fn test_precision_loss() {
+        use super::{CanonicalJSONError, Canonicalizer};
+
+        let strict = Canonicalizer::new().fail_on_precision_loss(true);
+        for input in &["1.0000000000000001", "-9007199254740993.0", "1e-400"] {
+            let value: Value = serde_json::from_str(input).unwrap();
+            assert!(
+                matches!(
+                    strict.to_string(&value),
+                    Err(CanonicalJSONError::PrecisionLoss(number)) if number == *input
+                ),
+                "{}",
+                input
+            );
+            assert!(
+                matches!(
+                    strict.canonicalize_slice(input.as_bytes()),
+                    Err(CanonicalJSONError::PrecisionLoss(_))
+                ),
+                "{}",
+                input
+            );
+        }
+
+        // only the value counts, not the way it is written
+        let exact = "[0.1, 1.50, -2.5e-3, 1E+2, 0.0, -0e10, 0e99999999999999999999, 9007199254740993, 5e-324]";
+        let value: Value = serde_json::from_str(exact).unwrap();
+        assert_eq!(
+            strict.to_string(&value).unwrap(),
+            Canonicalizer::new().to_string(&value).unwrap()
+        );
+        let rounded = strict
+            .max_significant_digits(2)
+            .to_string(&json!(1.2345))
+            .unwrap();
+        assert_eq!(rounded, "1.2E0");
+        // without the option, numbers are rounded silently
+        assert_eq!(
+            Canonicalizer::new()
+                .canonicalize_slice(b"1.0000000000000001")
+                .unwrap(),
+            "1.0E0"
+        );
+    }
+
+    #[cfg(feature = "arbitrary_precision")]
+    #[test]
+    fn test_exact_numbers() {
+        use super::Canonicalizer;
+
+        let exact = Canonicalizer::new().exact_numbers(true);
+        for (input, expected) in &[
+            ("1.0000000000000001", "1.0000000000000001E0"),
+            (
+                "-0.10000000000000000000000000001",
+                "-1.0000000000000000000000000001E-1",
+            ),
+            (
+                "123456789.123456789123456789",
+                "1.23456789123456789123456789E8",
+            ),
+            ("1e-400", "1.0E-400"),
+            ("-12.50e-3", "-1.25E-2"),
+            ("9007199254740993.0", "9007199254740993"),
+            ("18446744073709551615.00", "1.8446744073709551615E19"),
+            ("0.1", "1.0E-1"),
+            ("-0.0", "0.0E0"),
+            ("0e10", "0.0E0"),
+        ] {
+            let value: Value = serde_json::from_str(input).unwrap();
+            assert_eq!(exact.to_string(&value).unwrap(), *expected, "{}", input);
+        }
+
+        // numbers an f64 holds are written the same either way
+        let value: Value = serde_json::from_str("[0.1, 1.5, -2.5e-3, 1E+2, 1e21, 2e53]").unwrap();
+        assert_eq!(
+            exact.to_string(&value).unwrap(),
+            Canonicalizer::new().to_string(&value).unwrap()
+        );
+        assert!(exact
+            .clone()
+            .fail_on_precision_loss(true)
+            .canonicalize_slice(b"1.0000000000000001")
+            .is_ok());
+        // other options still apply
+        assert_eq!(
+            exact
+                .clone()
+                .max_significant_digits(3)
+                .canonicalize_slice(b"1.00000000000000000009")
+                .unwrap(),
+            "1.0E0"
+        );
+        assert_eq!(
+            exact
+                .plain_decimals(true)
+                .canonicalize_slice(b"1234.00000000000000000005")
+                .unwrap(),
+            "1234.00000000000000000005"
+        );
+    }
+
+    #[cfg(feature = "arbitrary_precision")]
+    #[test]
+    fn test_non_finite_policy_for_arbitrary_precision() {
+        use super::{CanonicalJSONError, Canonicalizer, NonFinite};
+
+        let value: Value =
+            serde_json::from_str("[1e400, -1e400, 1.5, 12345678901234567890123]").unwrap();
+        assert_eq!(
+            Canonicalizer::new().to_string(&value).unwrap(),
+            "[null,null,1.5E0,12345678901234567890123]"
         );
+        assert!(matches!(
+            Canonicalizer::new()
+                .non_finite(NonFinite::Error)
+                .to_string(&value),
+            Err(CanonicalJSONError::NonFiniteNumber(repr)) if repr.parse() == Ok(f64::INFINITY)
+        ));
     }
 }
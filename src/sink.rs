@@ -0,0 +1,108 @@
+//! Destinations of canonical JSON, behind a common trait.
+use crate::buf::CanonicalBuf;
+use crate::ser::{to_string, to_writer, CanonicalJSONError};
+use serde_json::Value;
+use std::io::Write;
+
+/// Anything canonical JSON values can be appended to.
+///
+/// The trait is object safe, so that code can take a `&mut dyn CanonicalSink`
+/// or a `Box<dyn CanonicalSink>` whatever the destination. Values are written
+/// back to back, with nothing in between.
+///
+/// # Example
+///
+/// ```
+/// use canonical_json::sink::CanonicalSink;
+/// use serde_json::json;
+///
+/// let mut output = String::new();
+/// let sink: &mut dyn CanonicalSink = &mut output;
+/// sink.write_value(&json!({"b": 1, "a": 2})).unwrap();
+/// assert_eq!(output, r#"{"a":2,"b":1}"#);
+/// ```
+pub trait CanonicalSink {
+    /// Appends the canonical form of `value`.
+    ///
+    /// On error, part of the output may have been written.
+    fn write_value(&mut self, value: &Value) -> Result<(), CanonicalJSONError>;
+}
+
+impl CanonicalSink for String {
+    fn write_value(&mut self, value: &Value) -> Result<(), CanonicalJSONError> {
+        self.push_str(&to_string(value)?);
+        Ok(())
+    }
+}
+
+impl CanonicalSink for Vec<u8> {
+    fn write_value(&mut self, value: &Value) -> Result<(), CanonicalJSONError> {
+        to_writer(self, value)
+    }
+}
+
+impl CanonicalSink for dyn Write + '_ {
+    fn write_value(&mut self, value: &Value) -> Result<(), CanonicalJSONError> {
+        to_writer(self, value)
+    }
+}
+
+impl CanonicalSink for CanonicalBuf {
+    fn write_value(&mut self, value: &Value) -> Result<(), CanonicalJSONError> {
+        CanonicalBuf::write_value(self, value)
+    }
+}
+
+impl<S: ?Sized + CanonicalSink> CanonicalSink for &mut S {
+    fn write_value(&mut self, value: &Value) -> Result<(), CanonicalJSONError> {
+        (**self).write_value(value)
+    }
+}
+
+impl<S: ?Sized + CanonicalSink> CanonicalSink for Box<S> {
+    fn write_value(&mut self, value: &Value) -> Result<(), CanonicalJSONError> {
+        (**self).write_value(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CanonicalSink;
+    use crate::buf::CanonicalBuf;
+    use serde_json::json;
+    use std::io::Write;
+
+    #[test]
+    fn test_boxed_sinks() {
+        let values = [json!({ "b": [1.5], "a": "é" }), json!(null)];
+        let expected = "{\"a\":\"é\",\"b\":[1.5E0]}null";
+
+        let mut file = vec![];
+        {
+            let writer: &mut dyn Write = &mut file;
+            let mut sinks: Vec<Box<dyn CanonicalSink + '_>> = vec![
+                Box::new(String::new()),
+                Box::new(Vec::<u8>::new()),
+                Box::new(CanonicalBuf::new()),
+                Box::new(writer),
+            ];
+            for sink in &mut sinks {
+                for value in &values {
+                    sink.write_value(value).unwrap();
+                }
+            }
+        }
+        assert_eq!(file, expected.as_bytes());
+    }
+
+    #[test]
+    fn test_sink_contents() {
+        let value = json!({ "z": null, "a": [{}] });
+        let mut string = String::new();
+        let mut bytes = vec![];
+        string.write_value(&value).unwrap();
+        bytes.write_value(&value).unwrap();
+        assert_eq!(string, r#"{"a":[{}],"z":null}"#);
+        assert_eq!(bytes, string.as_bytes());
+    }
+}
@@ -0,0 +1,546 @@
+//! Canonicalization of JSON documents read from streams.
+use crate::ser::{canonicalize_slice, CanonicalJSONError, Canonicalizer};
+use serde::ser::Serialize;
+use serde_json::Value;
+use std::convert::TryFrom;
+use std::io::{BufRead, Read, Write};
+
+/// Canonicalize newline-delimited JSON (NDJSON)
+///
+/// Every non-blank line of `reader` is parsed as a JSON document and written to
+/// `writer` in its canonical form, followed by a newline. Returns the number of
+/// documents written. A line holding anything but whitespace after its document
+/// (e.g. `{}x` or `1 2`) is an error, and so is an object repeating a key, as
+/// with [canonicalize_slice].
+///
+/// When `max_bytes` is given, the cumulative output (newlines included) may not
+/// exceed it: the line that would cross the budget is not written and
+/// [CanonicalJSONError::SizeLimitExceeded] reports its number.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::stream::canonicalize_ndjson;
+/// let input = "{\"b\": 1, \"a\": 2}\n[1.5]\n";
+/// let mut output = vec![];
+/// assert_eq!(canonicalize_ndjson(input.as_bytes(), &mut output, None).unwrap(), 2);
+/// assert_eq!(output, b"{\"a\":2,\"b\":1}\n[1.5E0]\n");
+/// ```
+pub fn canonicalize_ndjson<R, W>(
+    reader: R,
+    mut writer: W,
+    max_bytes: Option<usize>,
+) -> Result<usize, CanonicalJSONError>
+where
+    R: BufRead,
+    W: Write,
+{
+    let canonicalizer = Canonicalizer::new();
+    let mut scratch = vec![];
+    let mut total = 0;
+    let mut count = 0;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(CanonicalJSONError::Io)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        scratch.clear();
+        canonicalizer.write_canonical_slice(&mut scratch, line.as_bytes())?;
+        scratch.push(b'\n');
+
+        total += scratch.len();
+        if let Some(limit) = max_bytes {
+            if total > limit {
+                return Err(CanonicalJSONError::SizeLimitExceeded {
+                    limit,
+                    line: Some(index + 1),
+                });
+            }
+        }
+        writer.write_all(&scratch).map_err(CanonicalJSONError::Io)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Canonicalize the values of a fallible iterator, one per line
+///
+/// Every value is written to `writer` in its canonical form, followed by a
+/// newline, as [canonicalize_ndjson] does. Returns the number of values
+/// written. The first item that is an error stops the iteration and is
+/// returned as [CanonicalJSONError::Item], after the values before it have
+/// been written.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::stream::canonicalize_try_iter;
+/// # use serde_json::{json, Value};
+/// let items: Vec<Result<Value, String>> = vec![
+///     Ok(json!({"b": 1, "a": 2})),
+///     Err("connection reset".to_string()),
+///     Ok(json!(null)),
+/// ];
+/// let mut output = vec![];
+/// let error = canonicalize_try_iter(items.into_iter(), &mut output).unwrap_err();
+/// assert_eq!(error.to_string(), "source item error: connection reset");
+/// assert_eq!(output, b"{\"a\":2,\"b\":1}\n");
+/// ```
+pub fn canonicalize_try_iter<I, E, W>(iter: I, mut writer: W) -> Result<usize, CanonicalJSONError>
+where
+    I: Iterator<Item = Result<Value, E>>,
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    W: Write,
+{
+    let canonicalizer = Canonicalizer::new();
+    let mut scratch = vec![];
+    let mut count = 0;
+
+    for item in iter {
+        let value = item.map_err(|error| CanonicalJSONError::Item(error.into()))?;
+        scratch.clear();
+        canonicalizer.to_writer(&mut scratch, &value)?;
+        scratch.push(b'\n');
+        writer.write_all(&scratch).map_err(CanonicalJSONError::Io)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Serialize a JSON value in a length-prefixed frame
+///
+/// The frame is the length of the canonical output as a 4-byte big-endian
+/// integer, followed by the output itself. Fails with
+/// [CanonicalJSONError::SizeLimitExceeded] if the output is 4 GiB or more.
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::stream::to_writer_framed;
+/// # use serde_json::json;
+/// let mut frame = vec![];
+/// to_writer_framed(&mut frame, &json!({"b": 1, "a": 2})).unwrap();
+/// assert_eq!(frame, b"\0\0\0\x0d{\"a\":2,\"b\":1}");
+/// ```
+pub fn to_writer_framed<W, V>(writer: W, value: &V) -> Result<(), CanonicalJSONError>
+where
+    W: Write,
+    V: ?Sized + Serialize,
+{
+    let mut scratch = vec![];
+    Canonicalizer::new().to_writer(&mut scratch, value)?;
+    write_frame(writer, &scratch)
+}
+
+fn write_frame<W: Write>(mut writer: W, scratch: &[u8]) -> Result<(), CanonicalJSONError> {
+    let length =
+        u32::try_from(scratch.len()).map_err(|_| CanonicalJSONError::SizeLimitExceeded {
+            limit: u32::MAX as usize,
+            line: None,
+        })?;
+    writer
+        .write_all(&length.to_be_bytes())
+        .and_then(|()| writer.write_all(scratch))
+        .map_err(CanonicalJSONError::Io)
+}
+
+/// Canonicalize the JSON document making up the rest of a reader
+///
+/// The document is read in full before being parsed, and must not be followed
+/// by anything but whitespace. Errors of the reader are reported as
+/// [CanonicalJSONError::Io], malformed documents as
+/// [CanonicalJSONError::JSONError].
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::stream::canonicalize_from_reader;
+/// let input: &[u8] = b"{\"b\": [1.50], \"a\": null}\n";
+/// assert_eq!(canonicalize_from_reader(input).unwrap(), r#"{"a":null,"b":[1.5E0]}"#);
+/// ```
+pub fn canonicalize_from_reader<R>(mut reader: R) -> Result<String, CanonicalJSONError>
+where
+    R: Read,
+{
+    let mut document = vec![];
+    reader
+        .read_to_end(&mut document)
+        .map_err(CanonicalJSONError::Io)?;
+    canonicalize_slice(&document)
+}
+
+/// Canonicalize a JSON document read from a length-prefixed frame
+///
+/// Reads a frame as written by [to_writer_framed] (the document's length as a
+/// 4-byte big-endian integer, then the document), and writes its canonical form
+/// to `writer` in a new frame. The document is parsed as by [canonicalize_slice],
+/// which rejects repeated keys. A truncated frame fails with
+/// [CanonicalJSONError::Io], of kind [std::io::ErrorKind::UnexpectedEof].
+///
+/// # Examples
+/// ```rust
+/// # use canonical_json::stream::canonicalize_framed;
+/// let mut input: &[u8] = b"\0\0\0\x10{\"b\": 1, \"a\": 2}";
+/// let mut output = vec![];
+/// canonicalize_framed(&mut input, &mut output).unwrap();
+/// assert_eq!(output, b"\0\0\0\x0d{\"a\":2,\"b\":1}");
+/// ```
+pub fn canonicalize_framed<R, W>(reader: &mut R, writer: &mut W) -> Result<(), CanonicalJSONError>
+where
+    R: Read,
+    W: Write,
+{
+    let mut prefix = [0; 4];
+    reader
+        .read_exact(&mut prefix)
+        .map_err(CanonicalJSONError::Io)?;
+    let length = u32::from_be_bytes(prefix);
+    // read what is there rather than trust the prefix with an allocation
+    let mut document = vec![];
+    reader
+        .take(length.into())
+        .read_to_end(&mut document)
+        .map_err(CanonicalJSONError::Io)?;
+    if document.len() < length as usize {
+        return Err(CanonicalJSONError::Io(
+            std::io::ErrorKind::UnexpectedEof.into(),
+        ));
+    }
+    let mut scratch = vec![];
+    Canonicalizer::new().write_canonical_slice(&mut scratch, &document)?;
+    write_frame(writer, &scratch)
+}
+
+/// Writes an object member by member, without buffering nor sorting them
+///
+/// For trusted producers that already emit keys in canonical order (by the
+/// UTF-16 code units of the unescaped keys, without duplicates): members are written
+/// as soon as they are given, their values being canonicalized. The order is
+/// only checked in debug builds, where a member out of order panics; release
+/// builds write whatever they are given.
+///
+/// # Example
+///
+/// ```
+/// use canonical_json::stream::SortedObjectWriter;
+/// use serde_json::json;
+///
+/// let mut object = SortedObjectWriter::new(vec![]);
+/// object.member("a", &json!({"y": 1, "x": 2})).unwrap();
+/// object.member("b", &json!(null)).unwrap();
+/// assert_eq!(object.finish().unwrap(), br#"{"a":{"x":2,"y":1},"b":null}"#);
+/// ```
+#[derive(Debug)]
+pub struct SortedObjectWriter<W> {
+    writer: W,
+    canonicalizer: Canonicalizer,
+    empty: bool,
+    #[cfg(debug_assertions)]
+    last_key: Option<String>,
+}
+
+impl<W: Write> SortedObjectWriter<W> {
+    /// Object writer canonicalizing values with the default options.
+    pub fn new(writer: W) -> Self {
+        Self::with_canonicalizer(writer, Canonicalizer::new())
+    }
+
+    /// Object writer canonicalizing values with the options of `canonicalizer`.
+    pub fn with_canonicalizer(writer: W, canonicalizer: Canonicalizer) -> Self {
+        SortedObjectWriter {
+            writer,
+            canonicalizer,
+            empty: true,
+            #[cfg(debug_assertions)]
+            last_key: None,
+        }
+    }
+
+    /// Writes a member, whose key must come after the previous one.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if `key` does not sort after the previous key.
+    pub fn member<V>(&mut self, key: &str, value: &V) -> Result<(), CanonicalJSONError>
+    where
+        V: ?Sized + Serialize,
+    {
+        #[cfg(debug_assertions)]
+        {
+            if let Some(last_key) = &self.last_key {
+                assert!(
+                    crate::ser::cmp_utf16(last_key.as_bytes(), key.as_bytes()).is_lt(),
+                    "member {:?} written after {:?}",
+                    key,
+                    last_key
+                );
+            }
+            self.last_key = Some(key.to_string());
+        }
+        self.canonicalizer.check_finite(value)?;
+        let separator: &[u8] = if self.empty { b"{" } else { b"," };
+        self.empty = false;
+        self.writer
+            .write_all(separator)
+            .map_err(CanonicalJSONError::Io)?;
+
+        let mut serializer = serde_json::Serializer::with_formatter(
+            &mut self.writer,
+            self.canonicalizer.formatter(),
+        );
+        key.serialize(&mut serializer)?;
+        serializer
+            .into_inner()
+            .write_all(b":")
+            .map_err(CanonicalJSONError::Io)?;
+        let mut serializer = serde_json::Serializer::with_formatter(
+            &mut self.writer,
+            self.canonicalizer.formatter(),
+        );
+        value.serialize(&mut serializer)?;
+        Ok(())
+    }
+
+    /// Closes the object and returns the writer.
+    pub fn finish(mut self) -> Result<W, CanonicalJSONError> {
+        let end: &[u8] = if self.empty { b"{}" } else { b"}" };
+        self.writer.write_all(end).map_err(CanonicalJSONError::Io)?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        canonicalize_framed, canonicalize_from_reader, canonicalize_ndjson, canonicalize_try_iter,
+        to_writer_framed, SortedObjectWriter,
+    };
+    use crate::ser::{to_string, CanonicalJSONError};
+    use serde_json::json;
+    use std::io::Read;
+
+    #[test]
+    fn test_canonicalize_ndjson() {
+        let input = "{\"b\": 1, \"a\": [\"❤\"]}\n\n  \nnull\r\n1.5\n";
+        let mut output = vec![];
+
+        assert_eq!(
+            canonicalize_ndjson(input.as_bytes(), &mut output, None).unwrap(),
+            3
+        );
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"a\":[\"❤\"],\"b\":1}\nnull\n1.5E0\n"
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_try_iter() {
+        let mut items = vec![
+            Ok(json!({ "b": [1.5], "a": "❤" })),
+            Ok(json!(7)),
+            Err(std::io::Error::other("connection reset")),
+            Ok(json!(null)),
+        ]
+        .into_iter();
+        let iter: &mut dyn Iterator<Item = Result<_, _>> = &mut items;
+        let mut output = vec![];
+
+        let error = canonicalize_try_iter(iter, &mut output).unwrap_err();
+        assert!(
+            matches!(&error, CanonicalJSONError::Item(source) if source.to_string() == "connection reset"),
+            "{:?}",
+            error
+        );
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"a\":\"❤\",\"b\":[1.5E0]}\n7\n"
+        );
+        // the iteration stops at the error
+        assert_eq!(items.next().unwrap().unwrap(), json!(null));
+
+        let values = vec![Ok::<_, String>(json!([])), Ok(json!({}))];
+        let mut output = vec![];
+        assert_eq!(
+            canonicalize_try_iter(values.into_iter(), &mut output).unwrap(),
+            2
+        );
+        assert_eq!(output, b"[]\n{}\n");
+    }
+
+    #[test]
+    fn test_canonicalize_ndjson_budget() {
+        // the canonical lines are 4, 5 and 7 bytes long, newlines included
+        let input = "[ 1 ]\n[ 12 ]\n[ 1234 ]\n[ 1 ]\n";
+
+        let mut output = vec![];
+        assert_eq!(
+            canonicalize_ndjson(input.as_bytes(), &mut output, Some(9)).map_err(|e| e.to_string()),
+            Err("canonical output exceeds the limit of 9 bytes at line 3".to_string())
+        );
+        assert_eq!(output, b"[1]\n[12]\n");
+
+        let mut output = vec![];
+        assert!(matches!(
+            canonicalize_ndjson(input.as_bytes(), &mut output, Some(3)),
+            Err(CanonicalJSONError::SizeLimitExceeded {
+                limit: 3,
+                line: Some(1)
+            })
+        ));
+        assert!(output.is_empty());
+
+        assert_eq!(
+            canonicalize_ndjson(input.as_bytes(), vec![], Some(20)).unwrap(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_ndjson_invalid_line() {
+        for input in &["{}\n{\n", "{}x\n", "1 2\n", "[] []\n", "null\t,\n"] {
+            let mut output = vec![];
+            let result = canonicalize_ndjson(input.as_bytes(), &mut output, None);
+            match result {
+                Err(CanonicalJSONError::JSONError(error)) => {
+                    assert!(error.is_syntax() || error.is_eof())
+                }
+                _ => panic!("{:?} should be rejected", input),
+            }
+        }
+        // trailing whitespace is fine
+        assert_eq!(
+            canonicalize_ndjson("{} \t\r\n".as_bytes(), vec![], None).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_ndjson_duplicate_key() {
+        let input = "{\"a\": 1}\n{\"a\": 1, \"a\": 2}\n";
+        let mut output = vec![];
+        assert!(matches!(
+            canonicalize_ndjson(input.as_bytes(), &mut output, None),
+            Err(CanonicalJSONError::DuplicateKey(key)) if key == "a"
+        ));
+        assert_eq!(output, b"{\"a\":1}\n");
+    }
+
+    #[test]
+    fn test_canonicalize_ndjson_deep_line() {
+        let input = format!("[1]\n{}\n", "[".repeat(10000));
+        let mut output = vec![];
+        match canonicalize_ndjson(input.as_bytes(), &mut output, None) {
+            Err(CanonicalJSONError::JSONError(error)) => {
+                assert!(error.to_string().contains("recursion limit exceeded"))
+            }
+            other => panic!("expected a recursion error, got {:?}", other),
+        }
+        assert_eq!(output, b"[1]\n");
+    }
+
+    #[test]
+    fn test_sorted_object_writer() {
+        let value = json!({ "b": [1.5], "a": { "y": "❤", "x": null }, "\"": true });
+        let mut object = SortedObjectWriter::new(vec![]);
+        for (key, member) in value.as_object().unwrap() {
+            object.member(key, member).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(object.finish().unwrap()).unwrap(),
+            to_string(&value).unwrap()
+        );
+
+        let empty = SortedObjectWriter::new(vec![]);
+        assert_eq!(empty.finish().unwrap(), b"{}");
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "member \"a\" written after \"b\"")]
+    fn test_sorted_object_writer_out_of_order() {
+        let mut object = SortedObjectWriter::new(vec![]);
+        object.member("b", &json!(1)).unwrap();
+        object.member("a", &json!(2)).unwrap();
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "member \"a\" written after \"a\"")]
+    fn test_sorted_object_writer_duplicate() {
+        let mut object = SortedObjectWriter::new(vec![]);
+        object.member("a", &json!(1)).unwrap();
+        object.member("a", &json!(2)).unwrap();
+    }
+
+    #[test]
+    fn test_canonicalize_from_reader() {
+        let input = "{ \"id\": \"1\", \"title\": \"I \\u2764 testing\", \"n\": [1, 1e2] }";
+        assert_eq!(
+            canonicalize_from_reader(input.as_bytes()).unwrap(),
+            r#"{"id":"1","n":[1,1.0E2],"title":"I ❤ testing"}"#
+        );
+        assert!(matches!(
+            canonicalize_from_reader(&b"{\"a\": 1} {}"[..]),
+            Err(CanonicalJSONError::JSONError(_))
+        ));
+
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::ConnectionReset.into())
+            }
+        }
+        let error = canonicalize_from_reader(Failing).unwrap_err();
+        assert_eq!(error.io_kind(), Some(std::io::ErrorKind::ConnectionReset));
+    }
+
+    #[test]
+    fn test_canonicalize_framed() {
+        let value = json!({ "id": "1", "title": "I ❤ testing", "a": [1.5, null] });
+        let mut frame = vec![];
+        to_writer_framed(&mut frame, &value).unwrap();
+        let canonical = to_string(&value).unwrap();
+        assert_eq!(frame[..4], (canonical.len() as u32).to_be_bytes());
+        assert_eq!(frame[4..], *canonical.as_bytes());
+
+        // a canonical frame is its own canonical form
+        let mut output = vec![];
+        canonicalize_framed(&mut frame.as_slice(), &mut output).unwrap();
+        assert_eq!(output, frame);
+
+        // frames follow each other
+        let document = br#"[{"b": 2, "a": 1}]"#;
+        let mut input = (document.len() as u32).to_be_bytes().to_vec();
+        input.extend_from_slice(document);
+        input.extend_from_slice(&frame);
+        let mut reader = input.as_slice();
+        let mut output = vec![];
+        canonicalize_framed(&mut reader, &mut output).unwrap();
+        canonicalize_framed(&mut reader, &mut output).unwrap();
+        assert!(reader.is_empty());
+        let expected = [&b"\0\0\0\x0f"[..], br#"[{"a":1,"b":2}]"#, &frame].concat();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_canonicalize_framed_duplicate_key() {
+        let document = br#"{"a":1,"a":2}"#;
+        let mut input = (document.len() as u32).to_be_bytes().to_vec();
+        input.extend_from_slice(document);
+        let mut output = vec![];
+        assert!(matches!(
+            canonicalize_framed(&mut input.as_slice(), &mut output),
+            Err(CanonicalJSONError::DuplicateKey(key)) if key == "a"
+        ));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_canonicalize_framed_truncated() {
+        for input in &[&b""[..], b"\0\0", b"\0\0\0\x05[1,"] {
+            let mut reader = *input;
+            let error = canonicalize_framed(&mut reader, &mut vec![]).unwrap_err();
+            assert_eq!(error.io_kind(), Some(std::io::ErrorKind::UnexpectedEof));
+        }
+    }
+}
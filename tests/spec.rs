@@ -0,0 +1,80 @@
+//! Local spec-style fixtures.
+//!
+//! The cases are written in the layout of the test suite of gibson's
+//! Canonical JSON spec and expect the output the spec requires (e.g. `1` for
+//! the number `1.0`), but they are not the spec's own files.
+//! `tests/spec/test/<group>/<case>/` holds an `input.json` whose canonical
+//! form must be the bytes of `expected.json`, and every file of
+//! `tests/spec/malformed/` must be rejected.
+use canonical_json::ser::canonicalize_slice;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Cases this crate does not conform to yet, as `<group>/<case>`.
+///
+/// A listed case that starts passing fails the suite, so that it is removed
+/// from the list along with the fix.
+const KNOWN_FAILURES: &[&str] = &[
+    // control characters are escaped with lowercase hexadecimal digits
+    "2.string/4.control",
+    // serde_json rejects lone surrogates
+    "2.string/8.lone-surrogate",
+    // numbers parsed as floats are written with an exponent
+    "4.integer/1.no-negative-zero",
+    "4.integer/4.zero-fraction",
+    "4.integer/5.exponent",
+];
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/spec")
+}
+
+fn entries(directory: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(directory)
+        .unwrap_or_else(|error| panic!("cannot read {}: {}", directory.display(), error))
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    entries
+}
+
+fn canonical(input: &[u8]) -> Option<String> {
+    canonicalize_slice(input).ok()
+}
+
+#[test]
+fn test_spec_tokens() {
+    let mut failures = vec![];
+    let mut count = 0;
+    for group in entries(&fixtures().join("test")) {
+        for case in entries(&group) {
+            let name = case
+                .strip_prefix(fixtures().join("test"))
+                .unwrap()
+                .to_string_lossy()
+                .replace('\\', "/");
+            let input = fs::read(case.join("input.json")).unwrap();
+            let expected = fs::read(case.join("expected.json")).unwrap();
+
+            let conforms = canonical(&input).map(String::into_bytes) == Some(expected);
+            let known = KNOWN_FAILURES.contains(&name.as_str());
+            match (conforms, known) {
+                (false, false) => failures.push(format!("{} does not conform", name)),
+                (true, true) => failures.push(format!("{} conforms, unlist it", name)),
+                _ => {}
+            }
+            count += 1;
+        }
+    }
+    assert!(count > 0, "no fixtures found");
+    assert!(failures.is_empty(), "{:#?}", failures);
+}
+
+#[test]
+fn test_spec_malformed() {
+    let accepted: Vec<PathBuf> = entries(&fixtures().join("malformed"))
+        .into_iter()
+        .filter(|path| canonicalize_slice(&fs::read(path).unwrap()).is_ok())
+        .collect();
+    assert!(accepted.is_empty(), "{:#?}", accepted);
+}